@@ -0,0 +1,325 @@
+use crate::ihdr::{Ihdr, Interlace};
+use crate::Result;
+use std::fmt::{Display, Formatter};
+
+/// One of the seven passes of Adam7: the pixels whose column is `x` plus a multiple of
+/// `x_step` and whose row is `y` plus a multiple of `y_step`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pass {
+    pub x: u32,
+    pub y: u32,
+    pub x_step: u32,
+    pub y_step: u32,
+}
+
+impl Pass {
+    const fn new(x: u32, y: u32, x_step: u32, y_step: u32) -> Pass {
+        Pass {
+            x,
+            y,
+            x_step,
+            y_step,
+        }
+    }
+
+    /// Width and height of the reduced image this pass holds for an image of the given size.
+    /// Either is 0 if the image is too small to have pixels in this pass.
+    pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        let count = |size: u32, start: u32, step: u32| {
+            size.checked_sub(start)
+                .map_or(0, |rest| rest.div_ceil(step))
+        };
+        (
+            count(width, self.x, self.x_step),
+            count(height, self.y, self.y_step),
+        )
+    }
+}
+
+pub const PASSES: [Pass; 7] = [
+    Pass::new(0, 0, 8, 8),
+    Pass::new(4, 0, 8, 8),
+    Pass::new(0, 4, 4, 8),
+    Pass::new(2, 0, 4, 4),
+    Pass::new(0, 2, 2, 4),
+    Pass::new(1, 0, 2, 2),
+    Pass::new(0, 1, 1, 2),
+];
+
+/// Width and height of the images the scanlines of an image are stored as, in order: the
+/// whole image, or each pass that has any pixels if it is interlaced.
+pub fn sub_images(ihdr: &Ihdr) -> Vec<(u32, u32)> {
+    match ihdr.interlace {
+        Interlace::None => vec![(ihdr.width, ihdr.height)],
+        Interlace::Adam7 => PASSES
+            .iter()
+            .map(|pass| pass.size(ihdr.width, ihdr.height))
+            .filter(|&(width, height)| width > 0 && height > 0)
+            .collect(),
+    }
+}
+
+/// Size of the pixels of an image as a full raster.
+fn raster_size(ihdr: &Ihdr) -> usize {
+    ihdr.row_size(ihdr.width) * ihdr.height as usize
+}
+
+/// Size of the pixels of an image as its sub-images stored back to back.
+fn passes_size(ihdr: &Ihdr) -> usize {
+    sub_images(ihdr)
+        .iter()
+        .map(|&(width, height)| ihdr.row_size(width) * height as usize)
+        .sum()
+}
+
+/// Puts the unfiltered reduced images of an interlaced image, stored back to back, together
+/// into a full raster. Images that are not interlaced are returned as they are.
+pub fn deinterlace(ihdr: &Ihdr, passes: &[u8]) -> Result<Vec<u8>> {
+    check_size(passes_size(ihdr), passes)?;
+    if ihdr.interlace == Interlace::None {
+        return Ok(passes.to_vec());
+    }
+
+    let mut raster = vec![0; raster_size(ihdr)];
+    for_each_pixel(ihdr, |pass_row, x, raster_row, raster_x| {
+        copy_pixel(
+            &passes[pass_row..],
+            x,
+            &mut raster[raster_row..],
+            raster_x,
+            ihdr.bits_per_pixel(),
+        )
+    });
+    Ok(raster)
+}
+
+/// Reverses `deinterlace`, splitting a full raster into the reduced images of its passes.
+pub fn interlace(ihdr: &Ihdr, raster: &[u8]) -> Result<Vec<u8>> {
+    check_size(raster_size(ihdr), raster)?;
+    if ihdr.interlace == Interlace::None {
+        return Ok(raster.to_vec());
+    }
+
+    let mut passes = vec![0; passes_size(ihdr)];
+    for_each_pixel(ihdr, |pass_row, x, raster_row, raster_x| {
+        copy_pixel(
+            &raster[raster_row..],
+            raster_x,
+            &mut passes[pass_row..],
+            x,
+            ihdr.bits_per_pixel(),
+        )
+    });
+    Ok(passes)
+}
+
+/// Calls `f` for every pixel of every pass with the offset of its row within the passes stored
+/// back to back and its column within the pass, followed by the offset of its row within the
+/// full raster and its column there.
+fn for_each_pixel<F>(ihdr: &Ihdr, mut f: F)
+where
+    F: FnMut(usize, usize, usize, usize),
+{
+    let raster_row_size = ihdr.row_size(ihdr.width);
+    let mut offset = 0;
+    for pass in PASSES {
+        let (width, height) = pass.size(ihdr.width, ihdr.height);
+        if width == 0 || height == 0 {
+            continue;
+        }
+        let row_size = ihdr.row_size(width);
+        for y in 0..height {
+            let raster_y = (pass.y + y * pass.y_step) as usize;
+            for x in 0..width {
+                let raster_x = (pass.x + x * pass.x_step) as usize;
+                f(
+                    offset + y as usize * row_size,
+                    x as usize,
+                    raster_y * raster_row_size,
+                    raster_x,
+                );
+            }
+        }
+        offset += row_size * height as usize;
+    }
+}
+
+/// Copies pixel `from_x` of the row starting at `from` to pixel `to_x` of the row starting at
+/// `to`. Pixels smaller than a byte are packed from the most significant bit down.
+fn copy_pixel(from: &[u8], from_x: usize, to: &mut [u8], to_x: usize, bits_per_pixel: usize) {
+    if bits_per_pixel >= 8 {
+        let size = bits_per_pixel / 8;
+        to[to_x * size..(to_x + 1) * size]
+            .copy_from_slice(&from[from_x * size..(from_x + 1) * size]);
+        return;
+    }
+
+    let mask = (1u8 << bits_per_pixel) - 1;
+    let shift = |x: usize| 8 - bits_per_pixel - x * bits_per_pixel % 8;
+    let value = (from[from_x * bits_per_pixel / 8] >> shift(from_x)) & mask;
+    let byte = &mut to[to_x * bits_per_pixel / 8];
+    *byte = (*byte & !(mask << shift(to_x))) | (value << shift(to_x));
+}
+
+fn check_size(expected: usize, pixels: &[u8]) -> Result<()> {
+    if pixels.len() != expected {
+        return Err(Adam7Error::BadSize {
+            expected,
+            actual: pixels.len(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum Adam7Error {
+    BadSize { expected: usize, actual: usize },
+}
+
+impl std::error::Error for Adam7Error {}
+
+impl Display for Adam7Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Adam7Error::BadSize { expected, actual } => write!(
+                f,
+                "pixels should be {} bytes for the image but are {}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::ColorType;
+    use crate::Error;
+
+    fn ihdr(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Ihdr {
+        Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlace: Interlace::Adam7,
+        }
+    }
+
+    #[test]
+    fn test_pass_sizes() {
+        let sizes: Vec<(u32, u32)> = PASSES.iter().map(|pass| pass.size(8, 8)).collect();
+        assert_eq!(
+            sizes,
+            vec![(1, 1), (1, 1), (2, 1), (2, 2), (4, 2), (4, 4), (8, 4)]
+        );
+
+        // A single pixel only has a first pass.
+        let image = ihdr(1, 1, 8, ColorType::Rgb);
+        assert_eq!(sub_images(&image), vec![(1, 1)]);
+        assert_eq!(
+            sub_images(&ihdr(3, 2, 8, ColorType::Rgb)),
+            vec![(1, 1), (1, 1), (1, 1), (3, 1)]
+        );
+        assert_eq!(
+            sub_images(&Ihdr {
+                interlace: Interlace::None,
+                ..image
+            }),
+            vec![(1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_every_pixel_in_one_pass() {
+        let (width, height) = (13, 11);
+        let mut seen = vec![0; (width * height) as usize];
+        for pass in PASSES {
+            let (pass_width, pass_height) = pass.size(width, height);
+            for y in 0..pass_height {
+                for x in 0..pass_width {
+                    let raster_x = pass.x + x * pass.x_step;
+                    let raster_y = pass.y + y * pass.y_step;
+                    seen[(raster_y * width + raster_x) as usize] += 1;
+                }
+            }
+        }
+        assert!(seen.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn test_deinterlace_8x8() {
+        // Gray pixels holding the number of the pass they are in, laid out as in the spec.
+        let image = ihdr(8, 8, 8, ColorType::Grayscale);
+        let passes: Vec<u8> = PASSES
+            .iter()
+            .enumerate()
+            .flat_map(|(index, pass)| {
+                let (width, height) = pass.size(8, 8);
+                vec![index as u8 + 1; (width * height) as usize]
+            })
+            .collect();
+
+        let raster = deinterlace(&image, &passes).unwrap();
+        assert_eq!(&raster[..8], &[1, 6, 4, 6, 2, 6, 4, 6]);
+        assert_eq!(&raster[8..16], &[7; 8]);
+        assert_eq!(&raster[16..24], &[5, 6, 5, 6, 5, 6, 5, 6]);
+        assert_eq!(&raster[32..40], &[3, 6, 4, 6, 3, 6, 4, 6]);
+        assert_eq!(interlace(&image, &raster).unwrap(), passes);
+    }
+
+    #[test]
+    fn test_round_trip_every_format() {
+        for color_type in [
+            ColorType::Grayscale,
+            ColorType::Rgb,
+            ColorType::Indexed,
+            ColorType::GrayscaleAlpha,
+            ColorType::Rgba,
+        ] {
+            for &bit_depth in color_type.allowed_bit_depths() {
+                for (width, height) in [(1, 1), (5, 3), (17, 9)] {
+                    let image = ihdr(width, height, bit_depth, color_type);
+                    let raster: Vec<u8> = (0..raster_size(&image))
+                        .map(|i| (i * 89 % 256) as u8)
+                        .collect();
+                    // Padding bits at the end of a row are not pixels and are lost.
+                    let raster = deinterlace(&image, &interlace(&image, &raster).unwrap()).unwrap();
+                    let passes = interlace(&image, &raster).unwrap();
+                    assert_eq!(deinterlace(&image, &passes).unwrap(), raster);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sub_byte_pixels() {
+        // One bit per pixel, so every pass of the first row is a single bit.
+        let image = ihdr(8, 1, 1, ColorType::Grayscale);
+        let raster = [0b1010_0110];
+        let passes = interlace(&image, &raster).unwrap();
+        // Pass 1 has pixel 0, pass 2 pixel 4, pass 4 pixels 2 and 6, pass 6 the odd pixels.
+        assert_eq!(passes, vec![0b1000_0000, 0, 0b1100_0000, 0b0010_0000]);
+        assert_eq!(deinterlace(&image, &passes).unwrap(), raster);
+    }
+
+    #[test]
+    fn test_bad_size() {
+        let image = ihdr(8, 8, 8, ColorType::Grayscale);
+        assert!(matches!(
+            deinterlace(&image, &[0; 63]),
+            Err(Error::Adam7(Adam7Error::BadSize {
+                expected: 64,
+                actual: 63
+            }))
+        ));
+        assert!(matches!(
+            interlace(&image, &[0; 65]),
+            Err(Error::Adam7(Adam7Error::BadSize {
+                expected: 64,
+                actual: 65
+            }))
+        ));
+    }
+}
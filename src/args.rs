@@ -1,6 +1,6 @@
-use crate::commands;
-use crate::Result;
+use crate::commands::{self, Format, Mode, Payload};
 use clap::Parser;
+use png_msg::Result;
 use std::path::PathBuf;
 
 pub fn run() -> Result<()> {
@@ -14,19 +14,131 @@ fn execute(args: Args) -> Result<()> {
             chunk_type,
             message,
             output_path,
-        } => commands::encode(input_path, chunk_type, message, output_path),
+            output,
+            file,
+            stdin: _,
+            mode,
+            bits,
+            position,
+            max_chunk_size,
+            compress,
+            passphrase,
+            passphrase_file,
+            recipient,
+            sign,
+            signer_name,
+            sign_image,
+        } => {
+            let passphrase = commands::passphrase(passphrase, passphrase_file)?;
+
+            // Clap requires exactly one of a message, --file and --stdin.
+            let payload = match (file, message) {
+                (Some(file), _) => Payload::File(file),
+                (None, Some(message)) => Payload::Message(message),
+                (None, None) => Payload::Stdin,
+            };
+            let output_path = output_path.or(output);
+
+            commands::encode(
+                input_path,
+                chunk_type,
+                payload,
+                output_path,
+                mode,
+                bits,
+                position,
+                max_chunk_size,
+                compress,
+                passphrase,
+                recipient,
+                sign,
+                signer_name,
+                sign_image,
+            )
+        }
 
         Command::Decode {
             input_path,
             chunk_type,
-        } => commands::decode(input_path, chunk_type),
+            output_path,
+            all,
+            index,
+            lenient,
+            passphrase,
+            passphrase_file,
+            private_key,
+            format,
+            mode,
+        } => commands::decode(
+            input_path,
+            chunk_type,
+            output_path,
+            all,
+            index,
+            lenient,
+            commands::passphrase(passphrase, passphrase_file)?,
+            private_key,
+            format,
+            mode,
+        ),
 
         Command::Remove {
             input_path,
             chunk_type,
-        } => commands::remove(input_path, chunk_type),
+            all,
+            format,
+        } => commands::remove(input_path, chunk_type, all, format),
+
+        Command::Print {
+            input_path,
+            lenient,
+            format,
+        } => commands::print(input_path, lenient, format),
+
+        Command::Verify {
+            input_path,
+            chunk_type,
+            public_key,
+        } => commands::verify(input_path, chunk_type, public_key),
+
+        Command::Keygen {
+            output_path,
+            signing,
+        } => commands::keygen(output_path, signing),
 
-        Command::Print { input_path } => commands::print(input_path),
+        Command::Validate {
+            input_path,
+            lenient,
+        } => commands::validate(input_path, lenient),
+
+        Command::Strip { input_path } => commands::strip(input_path),
+
+        Command::Capacity { input_path } => commands::capacity(input_path),
+
+        Command::Text { command } => match command {
+            TextCommand::Add {
+                input_path,
+                keyword,
+                text,
+                compress,
+                international,
+                language,
+                translated_keyword,
+            } => commands::text_add(
+                input_path,
+                keyword,
+                text,
+                compress,
+                international,
+                language,
+                translated_keyword,
+            ),
+            TextCommand::List { input_path } => commands::text_list(input_path),
+            TextCommand::Remove {
+                input_path,
+                keyword,
+            } => commands::text_remove(input_path, keyword),
+        },
     }
 }
 
@@ -45,11 +157,71 @@ enum Command {
         #[clap(required = true)]
         chunk_type: String,
 
-        #[clap(required = true)]
-        message: String,
+        #[clap(required_unless_present_any = &["file", "stdin"], conflicts_with_all = &["file", "stdin"])]
+        message: Option<String>,
 
         #[clap(parse(from_os_str))]
         output_path: Option<PathBuf>,
+
+        /// Write the image to this path. Required to set an output path together with --file or
+        /// --stdin, which leave no room for the positional one
+        #[clap(long, parse(from_os_str), conflicts_with = "output-path")]
+        output: Option<PathBuf>,
+
+        /// Embed the contents of this file instead of a message
+        #[clap(long, parse(from_os_str))]
+        file: Option<PathBuf>,
+
+        /// Embed everything read from standard input instead of a message
+        #[clap(long, conflicts_with = "file")]
+        stdin: bool,
+
+        /// Where to hide the message: in a chunk, in trailing data after IEND or in the pixels,
+        /// where --position and --max-chunk-size do not apply
+        #[clap(long, arg_enum, default_value = "chunk")]
+        mode: Mode,
+
+        /// Number of low bits of each color sample to hide the message in with --mode lsb
+        #[clap(long, default_value = "1", possible_values = &["1", "2", "4"])]
+        bits: u8,
+
+        /// Where to place the chunk: before-iend, after-ihdr, after:<type>, index:<n> or end
+        #[clap(long, default_value = "before-iend")]
+        position: String,
+
+        /// Split the message across several chunks of at most this many bytes of data each
+        #[clap(long)]
+        max_chunk_size: Option<usize>,
+
+        /// Compress the message before embedding it: deflate or zstd
+        #[clap(long)]
+        compress: Option<String>,
+
+        /// Encrypt the message with a key derived from the passphrase in this file. Its final
+        /// line break is not part of the passphrase
+        #[clap(long, parse(from_os_str))]
+        passphrase_file: Option<PathBuf>,
+
+        /// Like --passphrase-file, but with the passphrase itself, which other users can see
+        /// in the process list and which may be kept in the shell history
+        #[clap(long, conflicts_with = "passphrase-file")]
+        passphrase: Option<String>,
+
+        /// Encrypt the message for the holder of this public key file
+        #[clap(long, parse(from_os_str), conflicts_with_all = &["passphrase", "passphrase-file"])]
+        recipient: Option<PathBuf>,
+
+        /// Sign the message with this signing key file. Only available with --mode chunk
+        #[clap(long, parse(from_os_str))]
+        sign: Option<PathBuf>,
+
+        /// Name to record as the signer
+        #[clap(long, requires = "sign", default_value = "")]
+        signer_name: String,
+
+        /// Also sign every other chunk in the image
+        #[clap(long, requires = "sign")]
+        sign_image: bool,
     },
 
     Decode {
@@ -58,6 +230,45 @@ enum Command {
 
         #[clap(required = true)]
         chunk_type: String,
+
+        /// Write the message or embedded file here instead of to standard output. If this is a
+        /// directory, an embedded file is written into it under its original name
+        #[clap(long = "output", parse(from_os_str), conflicts_with = "format")]
+        output_path: Option<PathBuf>,
+
+        /// List every message stored under the chunk type instead of only the first
+        #[clap(long, conflicts_with_all = &["index", "output-path"])]
+        all: bool,
+
+        /// Decode the message with this index, as numbered by --all
+        #[clap(long)]
+        index: Option<usize>,
+
+        /// Recover what we can from a damaged file instead of failing
+        #[clap(long)]
+        lenient: bool,
+
+        /// Decrypt a message that was encoded with a passphrase, read from this file
+        #[clap(long, parse(from_os_str))]
+        passphrase_file: Option<PathBuf>,
+
+        /// Like --passphrase-file, but with the passphrase itself, which other users can see
+        /// in the process list and which may be kept in the shell history
+        #[clap(long, conflicts_with = "passphrase-file")]
+        passphrase: Option<String>,
+
+        /// Decrypt a message that was encoded with --recipient using this private key file
+        #[clap(long, parse(from_os_str), conflicts_with_all = &["passphrase", "passphrase-file"])]
+        private_key: Option<PathBuf>,
+
+        /// How to write the output
+        #[clap(long, arg_enum, default_value = "text")]
+        format: Format,
+
+        /// Where the message was hidden. Chunk also finds messages in trailing data. The pixels
+        /// hold a single message, so lsb cannot be combined with --all, --index or --format
+        #[clap(long, arg_enum, default_value = "chunk")]
+        mode: Mode,
     },
 
     Remove {
@@ -66,10 +277,125 @@ enum Command {
 
         #[clap(required = true)]
         chunk_type: String,
+
+        /// Remove every message stored under the chunk type instead of only the first
+        #[clap(long)]
+        all: bool,
+
+        /// How to write the output
+        #[clap(long, arg_enum, default_value = "text")]
+        format: Format,
     },
 
     Print {
         #[clap(required = true, parse(from_os_str))]
         input_path: PathBuf,
+
+        /// Recover what we can from a damaged file instead of failing
+        #[clap(long)]
+        lenient: bool,
+
+        /// How to write the output
+        #[clap(long, arg_enum, default_value = "text")]
+        format: Format,
+    },
+
+    /// Check the signature of a message that was encoded with --sign
+    Verify {
+        #[clap(required = true, parse(from_os_str))]
+        input_path: PathBuf,
+
+        #[clap(required = true)]
+        chunk_type: String,
+
+        /// Require the message to be signed by the holder of this public key file
+        #[clap(long, parse(from_os_str))]
+        public_key: Option<PathBuf>,
+    },
+
+    /// Generate a key pair for --recipient and --private-key
+    Keygen {
+        /// Where to write the private key, the public key is written alongside it with a
+        /// .pub extension
+        #[clap(required = true, parse(from_os_str))]
+        output_path: PathBuf,
+
+        /// Generate a key pair for --sign and verify instead
+        #[clap(long)]
+        signing: bool,
+    },
+
+    /// Check that the chunks of an image are ordered and counted as the PNG spec requires
+    Validate {
+        #[clap(required = true, parse(from_os_str))]
+        input_path: PathBuf,
+
+        /// Check whatever chunks can be recovered from a damaged file instead of failing
+        #[clap(long)]
+        lenient: bool,
+    },
+
+    /// Remove any data appended to the image after IEND
+    Strip {
+        #[clap(required = true, parse(from_os_str))]
+        input_path: PathBuf,
+    },
+
+    /// Show how many bytes of message the image can hold in each way of hiding it
+    Capacity {
+        #[clap(required = true, parse(from_os_str))]
+        input_path: PathBuf,
+    },
+
+    /// Add, list or remove the tEXt, zTXt and iTXt metadata of an image
+    Text {
+        #[clap(subcommand)]
+        command: TextCommand,
+    },
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum TextCommand {
+    /// Add a text entry, stored as tEXt unless --compress or --international is given
+    Add {
+        #[clap(required = true, parse(from_os_str))]
+        input_path: PathBuf,
+
+        #[clap(required = true)]
+        keyword: String,
+
+        #[clap(required = true)]
+        text: String,
+
+        /// Compress the text, storing it as zTXt or as compressed iTXt
+        #[clap(long)]
+        compress: bool,
+
+        /// Store the text as UTF-8 in an iTXt chunk instead of as Latin-1
+        #[clap(long)]
+        international: bool,
+
+        /// Language of the text, such as en or pt-BR
+        #[clap(long, requires = "international")]
+        language: Option<String>,
+
+        /// The keyword translated into the language of the text
+        #[clap(long, requires = "international")]
+        translated_keyword: Option<String>,
+    },
+
+    /// List every text entry in the image
+    List {
+        #[clap(required = true, parse(from_os_str))]
+        input_path: PathBuf,
+    },
+
+    /// Remove every text entry with the given keyword
+    Remove {
+        #[clap(required = true, parse(from_os_str))]
+        input_path: PathBuf,
+
+        #[clap(required = true)]
+        keyword: String,
     },
 }
@@ -0,0 +1,238 @@
+use crate::chunk::Chunk;
+use crate::compression::{self, Compression};
+use crate::crypto::{self, Encryption};
+use crate::fragment;
+use crate::lsb;
+use crate::png::Png;
+use crate::sealed::{self, Kind};
+use crate::Result;
+use std::fmt::{Display, Formatter};
+
+/// A way of hiding a message in an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Embedding {
+    /// In a chunk of its own.
+    Chunk,
+    /// As the text of a `tEXt` chunk.
+    Text,
+    /// In the `bits` least significant bits of every color sample, see `lsb::embed`.
+    Lsb { bits: u8 },
+    /// After `IEND`.
+    Trailing,
+}
+
+impl Embedding {
+    /// Every embedding, with each number of bits per sample `lsb::embed` supports.
+    pub const ALL: [Embedding; 6] = [
+        Embedding::Chunk,
+        Embedding::Text,
+        Embedding::Lsb { bits: 1 },
+        Embedding::Lsb { bits: 2 },
+        Embedding::Lsb { bits: 4 },
+        Embedding::Trailing,
+    ];
+}
+
+impl Display for Embedding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Embedding::Chunk => write!(f, "chunk"),
+            Embedding::Text => write!(f, "tEXt chunk"),
+            Embedding::Lsb { bits: 1 } => write!(f, "LSB, 1 bit per sample"),
+            Embedding::Lsb { bits } => write!(f, "LSB, {} bits per sample", bits),
+            Embedding::Trailing => write!(f, "trailing data"),
+        }
+    }
+}
+
+impl Png {
+    /// Number of message bytes `embedding` can hide in this image once `ops::seal_message` has
+    /// sealed them without compression or encryption, or `None` if there is no limit. Take
+    /// `overhead` into account for other ways of sealing. For chunks this is the limit of a
+    /// single chunk: a longer message can still be split across several with `fragment::split`,
+    /// at the cost of a `fragment::HEADER_SIZE` header in each.
+    pub fn capacity(&self, embedding: Embedding) -> Result<Option<usize>> {
+        let room = match embedding {
+            Embedding::Chunk => Chunk::MAX_DATA_LENGTH,
+            // The shortest keyword is a single character, followed by its null separator.
+            Embedding::Text => Chunk::MAX_DATA_LENGTH - 2,
+            Embedding::Lsb { bits } => lsb::capacity(&self.ihdr()?, bits)?,
+            Embedding::Trailing => return Ok(None),
+        };
+        Ok(Some(room.saturating_sub(overhead(None, None))))
+    }
+}
+
+/// Number of bytes `ops::seal_message` adds to a message sealed with `compression` and
+/// `encryption`, leaving aside how much compressing it saves.
+pub fn overhead(compression: Option<Compression>, encryption: Option<&Encryption>) -> usize {
+    let mut overhead = sealed::HEADER_SIZE;
+    if compression.is_some() {
+        overhead += sealed::HEADER_SIZE + compression::HEADER_SIZE;
+    }
+    if let Some(encryption) = encryption {
+        overhead += sealed::HEADER_SIZE + crypto::overhead(encryption.scheme()).unwrap_or(0);
+    }
+    overhead
+}
+
+/// Number of bytes at the start of `data` that are headers rather than the message: the
+/// fragment header, if there is one, and the headers `ops::seal_message` wrapped the message in,
+/// as far as the outer layer tells. The innermost header is counted even when it is compressed
+/// or encrypted, to agree with `overhead`.
+fn header_size(data: &[u8]) -> usize {
+    let fragment_header = match fragment::is_fragment(data) {
+        true => fragment::HEADER_SIZE.min(data.len()),
+        false => 0,
+    };
+    let seal = match sealed::unwrap(&data[fragment_header..]) {
+        Ok((Kind::Compressed, _)) => 2 * sealed::HEADER_SIZE + compression::HEADER_SIZE,
+        Ok((Kind::Encrypted, body)) => {
+            let encryption = crypto::scheme(body).and_then(crypto::overhead);
+            2 * sealed::HEADER_SIZE + encryption.unwrap_or(0)
+        }
+        Ok(_) => sealed::HEADER_SIZE,
+        Err(_) => 0,
+    };
+    fragment_header + seal
+}
+
+#[derive(Debug)]
+pub enum CapacityError {
+    TooLarge {
+        embedding: Embedding,
+        needed: usize,
+        capacity: usize,
+    },
+}
+
+impl CapacityError {
+    /// Refuses `data`, which does not fit in the `room` bytes `embedding` has for it. Like
+    /// `Png::capacity`, the error counts message bytes, leaving out the headers `data` starts
+    /// with.
+    pub(crate) fn too_large(embedding: Embedding, data: &[u8], room: usize) -> CapacityError {
+        let headers = header_size(data);
+        CapacityError::TooLarge {
+            embedding,
+            needed: data.len().saturating_sub(headers),
+            capacity: room.saturating_sub(headers),
+        }
+    }
+}
+
+impl std::error::Error for CapacityError {}
+
+impl Display for CapacityError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CapacityError::TooLarge {
+                embedding,
+                needed,
+                capacity,
+            } => write!(
+                f,
+                "message needs {} bytes but {} can only hold {}",
+                needed, embedding, capacity
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsb::LsbError;
+    use crate::ops;
+    use crate::png::sample;
+    use crate::sealed::Content;
+    use crate::Error;
+
+    #[test]
+    fn test_capacity() {
+        let png = sample();
+        let capacities: Vec<Option<usize>> = Embedding::ALL
+            .iter()
+            .map(|&embedding| png.capacity(embedding).unwrap())
+            .collect();
+
+        assert_eq!(
+            capacities,
+            vec![
+                Some(2147483642),
+                Some(2147483640),
+                Some(93732),
+                Some(187469),
+                Some(374943),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overhead() {
+        let message = Content::Message(b"Hello".to_vec());
+        let encryption = Encryption::Passphrase("hunter2");
+        for (compression, encryption) in [
+            (None, None),
+            (Some(Compression::Deflate), None),
+            (None, Some(&encryption)),
+        ] {
+            let data = ops::seal_message(&message, compression, encryption).unwrap();
+            if compression.is_none() {
+                assert_eq!(data.len(), 5 + overhead(compression, encryption));
+            }
+            assert_eq!(header_size(&data), overhead(compression, encryption));
+        }
+
+        let data = ops::seal_message(&Content::Message(vec![0; 30]), None, None).unwrap();
+        let fragments = fragment::split(&data, fragment::HEADER_SIZE + 10).unwrap();
+        assert_eq!(
+            header_size(&fragments[0]),
+            fragment::HEADER_SIZE + sealed::HEADER_SIZE
+        );
+        assert_eq!(header_size(&fragments[1]), fragment::HEADER_SIZE);
+        assert_eq!(header_size(b"Hello"), 0);
+    }
+
+    #[test]
+    fn test_too_large() {
+        let data = ops::seal_message(&Content::Message(vec![0; 11]), None, None).unwrap();
+        assert!(matches!(
+            CapacityError::too_large(Embedding::Chunk, &data, 15),
+            CapacityError::TooLarge {
+                needed: 11,
+                capacity: 10,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_lsb() {
+        let png = Png::from_chunks(vec![]);
+        assert!(matches!(
+            png.capacity(Embedding::Lsb { bits: 1 }),
+            Err(Error::Ihdr(_))
+        ));
+        assert!(png.capacity(Embedding::Chunk).is_ok());
+
+        let png = sample();
+        assert!(matches!(
+            png.capacity(Embedding::Lsb { bits: 8 }),
+            Err(Error::Lsb(LsbError::BadBitsPerSample(8)))
+        ));
+    }
+
+    #[test]
+    fn test_display() {
+        let err = CapacityError::TooLarge {
+            embedding: Embedding::Lsb { bits: 2 },
+            needed: 100,
+            capacity: 42,
+        };
+        assert_eq!(
+            err.to_string(),
+            "message needs 100 bytes but LSB, 2 bits per sample can only hold 42"
+        );
+    }
+}
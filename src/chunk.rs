@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
+use crate::chunk_ref::{self, ChunkRef};
 use crate::chunk_type::ChunkType;
 use crate::{Error, Result};
 use std::fmt::{Display, Formatter};
@@ -8,11 +9,18 @@ use std::fmt::{Display, Formatter};
 pub struct Chunk {
     chunk_type: ChunkType,
     data: Vec<u8>,
+    /// The CRC the chunk was read with, written back in place of a freshly computed one so
+    /// that a chunk parsed leniently with a wrong CRC round-trips unchanged.
+    stored_crc: Option<u32>,
 }
 
 impl Chunk {
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
-        Self { chunk_type, data }
+        Self {
+            chunk_type,
+            data,
+            stored_crc: None,
+        }
     }
 
     pub const CHUNK_LENGTH_FIELD_SIZE: usize = 4;
@@ -22,6 +30,9 @@ impl Chunk {
     pub const CHUNK_META_SIZE: usize =
         Self::CHUNK_LENGTH_FIELD_SIZE + Self::CHUNK_TYPE_FIELD_SIZE + Self::CHUNK_CRC_FIELD_SIZE;
 
+    /// Largest amount of data the spec allows in a chunk, 2^31 - 1 bytes.
+    pub const MAX_DATA_LENGTH: usize = i32::MAX as usize;
+
     pub fn length(&self) -> usize {
         self.data.len()
     }
@@ -35,14 +46,13 @@ impl Chunk {
     }
 
     pub fn crc(&self) -> u32 {
-        let bytes: Vec<u8> = self
-            .chunk_type
-            .bytes()
-            .iter()
-            .chain(self.data.iter())
-            .copied()
-            .collect();
-        crc::crc32::checksum_ieee(&bytes)
+        chunk_ref::crc(&self.chunk_type, &self.data)
+    }
+
+    /// The CRC stored in the file this chunk was parsed from, which only differs from `crc`
+    /// if the chunk is damaged. `None` for chunks that were created rather than parsed.
+    pub fn stored_crc(&self) -> Option<u32> {
+        self.stored_crc
     }
 
     pub fn data_as_string(&self) -> Result<String> {
@@ -57,51 +67,61 @@ impl Chunk {
             .iter()
             .chain(self.chunk_type.bytes().iter())
             .chain(self.data.iter())
-            .chain(self.crc().to_be_bytes().iter())
+            .chain(
+                self.stored_crc
+                    .unwrap_or_else(|| self.crc())
+                    .to_be_bytes()
+                    .iter(),
+            )
             .copied()
             .collect()
     }
 }
 
+impl Chunk {
+    /// Parses a chunk without verifying its CRC, returning the chunk alongside the CRC that
+    /// was stored in `value`.
+    pub(crate) fn parse_unchecked(value: &[u8]) -> Result<(Chunk, u32)> {
+        let (chunk, stored_crc) = ChunkRef::parse_unchecked(value)?;
+        let mut chunk = chunk.to_chunk();
+        chunk.stored_crc = Some(stored_crc);
+        Ok((chunk, stored_crc))
+    }
+}
+
 impl TryFrom<&[u8]> for Chunk {
     type Error = Error;
 
     fn try_from(value: &[u8]) -> Result<Self> {
-        let (length, value) = value.split_at(Self::CHUNK_LENGTH_FIELD_SIZE);
-        let length = u32::from_be_bytes(length.try_into()?) as usize;
-
-        let (chunk_type, value) = value.split_at(Self::CHUNK_TYPE_FIELD_SIZE);
-        let chunk_type: [u8; Self::CHUNK_LENGTH_FIELD_SIZE] = chunk_type.try_into()?;
-        let chunk_type = ChunkType::try_from(chunk_type)?;
-
-        if !chunk_type.is_valid() {
-            return Err(Box::from(ChunkParseError::BadChunkType));
-        }
-
-        let (data, value) = value.split_at(length);
-
-        let (crc, value) = value.split_at(Self::CHUNK_CRC_FIELD_SIZE);
-
-        let supplied_chunk = Self {
-            chunk_type,
-            data: data.into(),
-        };
-
+        let (supplied_chunk, expected_crc) = Self::parse_unchecked(value)?;
         let actual_crc = supplied_chunk.crc();
-        let expected_crc = u32::from_be_bytes(crc.try_into()?);
 
         if actual_crc != expected_crc {
-            return Err(Box::from(ChunkParseError::BadCrc(expected_crc, actual_crc)));
+            return Err(ChunkParseError::BadCrc {
+                expected: expected_crc,
+                actual: actual_crc,
+            }
+            .into());
         }
 
         Ok(supplied_chunk)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ChunkParseError {
-    BadCrc(u32, u32),
-    BadChunkType,
+    BadCrc {
+        expected: u32,
+        actual: u32,
+    },
+    BadChunkType([u8; Chunk::CHUNK_TYPE_FIELD_SIZE]),
+    /// The input ended before a field was complete. `offset` is where the field starts,
+    /// relative to the start of the chunk.
+    Truncated {
+        offset: usize,
+        needed: usize,
+        available: usize,
+    },
 }
 
 impl std::error::Error for ChunkParseError {}
@@ -109,25 +129,31 @@ impl std::error::Error for ChunkParseError {}
 impl Display for ChunkParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
-            ChunkParseError::BadCrc(expected, actual) => {
+            ChunkParseError::BadCrc { expected, actual } => {
                 write!(f, "expected CRC of {} but got CRC of {}", expected, actual)
             }
-            ChunkParseError::BadChunkType => write!(f, "unrecognized chunk type"),
+            ChunkParseError::BadChunkType(bytes) => {
+                write!(f, "unrecognized chunk type {:?}", bytes)
+            }
+            ChunkParseError::Truncated {
+                offset,
+                needed,
+                available,
+            } => write!(
+                f,
+                "truncated chunk, needed {} bytes at offset {} but only {} remain",
+                needed, offset, available
+            ),
         }
     }
 }
 
 impl Display for Chunk {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "Chunk {{",)?;
-        writeln!(f, "  Length: {}", self.length())?;
-        writeln!(f, "  Type: {}", self.chunk_type())?;
-        writeln!(f, "  Data: {} bytes", self.data().len())?;
-        writeln!(f, "  Crc: {}", self.crc())?;
-        writeln!(f, "}}",)?;
-        Ok(())
+        ChunkRef::from(self).fmt(f)
     }
 }
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -237,6 +263,35 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_truncated_chunk_from_bytes() {
+        let chunk = testing_chunk();
+        let bytes = chunk.as_bytes();
+
+        for len in 0..bytes.len() {
+            assert!(Chunk::try_from(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_oversized_length_from_bytes() {
+        let mut bytes = testing_chunk().as_bytes();
+        bytes[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        match Chunk::try_from(bytes.as_ref()) {
+            Err(Error::Chunk {
+                source:
+                    ChunkParseError::Truncated {
+                        offset: 8,
+                        needed,
+                        available: 46,
+                    },
+                ..
+            }) => assert_eq!(needed, u32::MAX as usize),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -0,0 +1,215 @@
+use crate::chunk::{Chunk, ChunkParseError};
+use crate::chunk_type::ChunkType;
+use crate::compression;
+use crate::fragment::Fragment;
+use crate::ihdr::Ihdr;
+use crate::png::Png;
+use crate::sealed::{self, Kind};
+use crate::{Error, Result};
+use std::fmt::{Display, Formatter};
+
+/// A chunk borrowed from the bytes it was parsed from, for read-only access without copying
+/// the chunk data.
+pub struct ChunkRef<'a> {
+    chunk_type: ChunkType,
+    data: &'a [u8],
+}
+
+impl<'a> ChunkRef<'a> {
+    pub fn length(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn crc(&self) -> u32 {
+        crc(&self.chunk_type, self.data)
+    }
+
+    pub fn data_as_str(&self) -> Result<&'a str> {
+        Ok(std::str::from_utf8(self.data)?)
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(self.chunk_type, self.data.to_vec())
+    }
+
+    /// Parses a chunk without verifying its CRC, returning the chunk alongside the CRC that
+    /// was stored in `value`.
+    pub(crate) fn parse_unchecked(value: &'a [u8]) -> Result<(ChunkRef<'a>, u32)> {
+        let mut offset = 0;
+
+        let length = take(value, &mut offset, Chunk::CHUNK_LENGTH_FIELD_SIZE)?;
+        let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+
+        let chunk_type = take(value, &mut offset, Chunk::CHUNK_TYPE_FIELD_SIZE)?;
+        let chunk_type: [u8; Chunk::CHUNK_TYPE_FIELD_SIZE] = chunk_type.try_into().unwrap();
+        let chunk_type = ChunkType::try_from(chunk_type)
+            .ok()
+            .filter(ChunkType::is_valid)
+            .ok_or(ChunkParseError::BadChunkType(chunk_type))?;
+
+        let data = take(value, &mut offset, length)?;
+
+        let crc = take(value, &mut offset, Chunk::CHUNK_CRC_FIELD_SIZE)?;
+        let stored_crc = u32::from_be_bytes(crc.try_into().unwrap());
+
+        Ok((ChunkRef { chunk_type, data }, stored_crc))
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for ChunkRef<'a> {
+    type Error = Error;
+
+    fn try_from(value: &'a [u8]) -> Result<Self> {
+        let (chunk, expected) = Self::parse_unchecked(value)?;
+        let actual = chunk.crc();
+
+        if actual != expected {
+            return Err(ChunkParseError::BadCrc { expected, actual }.into());
+        }
+
+        Ok(chunk)
+    }
+}
+
+impl<'a> From<&'a Chunk> for ChunkRef<'a> {
+    fn from(chunk: &'a Chunk) -> Self {
+        ChunkRef {
+            chunk_type: *chunk.chunk_type(),
+            data: chunk.data(),
+        }
+    }
+}
+
+impl Display for ChunkRef<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Chunk {{",)?;
+        writeln!(f, "  Length: {}", self.length())?;
+        writeln!(f, "  Type: {}", self.chunk_type())?;
+        writeln!(f, "  Data: {} bytes", self.data().len())?;
+        if self.chunk_type().to_string() == Png::IHDR {
+            match Ihdr::try_from(self.data()) {
+                Ok(ihdr) => writeln!(f, "  Image: {}", ihdr)?,
+                Err(err) => writeln!(f, "  Image: invalid, {}", err)?,
+            }
+        }
+        if let Ok(fragment) = Fragment::try_from(self.data()) {
+            writeln!(
+                f,
+                "  Fragment: {} of {}",
+                fragment.index() + 1,
+                fragment.count()
+            )?;
+        }
+        let compressed = match sealed::unwrap(self.data()) {
+            Ok((Kind::Compressed, body)) => compression::info(body),
+            _ => None,
+        };
+        if let Some(info) = compressed {
+            writeln!(
+                f,
+                "  Compressed: {}, {} bytes uncompressed",
+                info.algorithm, info.original_size
+            )?;
+        }
+        writeln!(f, "  Crc: {}", self.crc())?;
+        writeln!(f, "}}",)?;
+        Ok(())
+    }
+}
+
+/// Computes a chunk CRC over the type and data without concatenating them first.
+pub(crate) fn crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    use crc::crc32::{self, Hasher32};
+
+    let mut digest = crc32::Digest::new(crc32::IEEE);
+    digest.write(&chunk_type.bytes());
+    digest.write(data);
+    digest.sum32()
+}
+
+/// Reads the next `needed` bytes of `value` starting at `offset`, advancing `offset` past them.
+fn take<'a>(
+    value: &'a [u8],
+    offset: &mut usize,
+    needed: usize,
+) -> std::result::Result<&'a [u8], ChunkParseError> {
+    let available = value.len() - *offset;
+    if needed > available {
+        return Err(ChunkParseError::Truncated {
+            offset: *offset,
+            needed,
+            available,
+        });
+    }
+
+    let bytes = &value[*offset..*offset + needed];
+    *offset += needed;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let data = b"This is where your secret message will be!".to_vec();
+        Chunk::new(chunk_type, data).as_bytes()
+    }
+
+    #[test]
+    fn test_chunk_ref_from_bytes() {
+        let bytes = testing_bytes();
+        let chunk = ChunkRef::try_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.chunk_type().to_string(), "RuSt");
+        assert_eq!(
+            chunk.data_as_str().unwrap(),
+            "This is where your secret message will be!"
+        );
+        assert_eq!(chunk.crc(), 2882656334);
+        assert_eq!(chunk.data().as_ptr(), bytes[8..].as_ptr());
+    }
+
+    #[test]
+    fn test_chunk_ref_to_chunk() {
+        let bytes = testing_bytes();
+        let chunk = ChunkRef::try_from(bytes.as_slice()).unwrap().to_chunk();
+        assert_eq!(chunk.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_display_matches_chunk() {
+        let bytes = testing_bytes();
+        let chunk = Chunk::try_from(bytes.as_slice()).unwrap();
+        let chunk_ref = ChunkRef::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(chunk.to_string(), chunk_ref.to_string());
+        assert!(chunk.to_string().contains("Type: RuSt"));
+    }
+
+    #[test]
+    fn test_chunk_ref_bad_crc() {
+        let mut bytes = testing_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        assert!(ChunkRef::try_from(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_chunk_ref_truncated() {
+        let bytes = testing_bytes();
+        for len in 0..bytes.len() {
+            assert!(ChunkRef::try_from(&bytes[..len]).is_err());
+        }
+    }
+}
@@ -6,7 +6,7 @@ use std::str::FromStr;
 
 const CHUNK_TYPE_MAX_SIZE: usize = 4;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ChunkType {
     data: [u8; CHUNK_TYPE_MAX_SIZE],
 }
@@ -58,7 +58,7 @@ impl TryFrom<[u8; CHUNK_TYPE_MAX_SIZE]> for ChunkType {
     fn try_from(value: [u8; CHUNK_TYPE_MAX_SIZE]) -> Result<Self> {
         for b in value.iter() {
             if !b.is_ascii_alphabetic() {
-                return Err(Box::from(ChunkTypeError::InvalidCharacter(*b)));
+                return Err(ChunkTypeError::InvalidCharacter(*b).into());
             }
         }
         Ok(ChunkType { data: value })
@@ -69,18 +69,20 @@ impl FromStr for ChunkType {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self> {
-        if s.len() != CHUNK_TYPE_MAX_SIZE && s.len() != 0 {
-            return Err(Box::from(ChunkTypeError::InvalidLength(s.len())));
+        if s.len() != CHUNK_TYPE_MAX_SIZE && !s.is_empty() {
+            return Err(ChunkTypeError::InvalidLength(s.len()).into());
         }
 
         let bytes_slice = s.as_bytes();
         for b in bytes_slice.iter() {
             if !b.is_ascii_alphabetic() {
-                return Err(Box::from(ChunkTypeError::InvalidCharacter(*b)));
+                return Err(ChunkTypeError::InvalidCharacter(*b).into());
             }
         }
 
-        let bytes_array = bytes_slice.try_into()?;
+        let bytes_array = bytes_slice
+            .try_into()
+            .map_err(|_| ChunkTypeError::InvalidLength(s.len()))?;
         Ok(ChunkType { data: bytes_array })
     }
 }
@@ -197,6 +199,18 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_errors() {
+        assert!(matches!(
+            ChunkType::from_str("RuStY"),
+            Err(Error::ChunkType(ChunkTypeError::InvalidLength(5)))
+        ));
+        assert!(matches!(
+            ChunkType::from_str("Ru1t"),
+            Err(Error::ChunkType(ChunkTypeError::InvalidCharacter(b'1')))
+        ));
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -1,66 +1,662 @@
-use crate::png::{Chunk, ChunkType, Png};
-use crate::Result;
-use std::fs;
+use memmap2::Mmap;
+use png_msg::capacity::Embedding;
+use png_msg::compression::Compression;
+use png_msg::crypto::{Decryption, Encryption, PrivateKey};
+use png_msg::envelope::{self, Envelope};
+use png_msg::fragment;
+use png_msg::lsb::LsbError;
+use png_msg::ops::{self, StoredMessage};
+use png_msg::png::PngError;
+use png_msg::report::{self, ChunkReport, MessageReport};
+use png_msg::sealed::{self, Content};
+use png_msg::signature::{SignatureError, SigningKey};
+use png_msg::text::{self, TextEntry};
+use png_msg::trailer::TrailingData;
+use png_msg::Result;
+use png_msg::{ChunkPosition, Png, PngRef};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+/// How `print`, `decode` and `remove` write what they found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+pub enum Format {
+    Text,
+    /// A single JSON document.
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+}
+
+impl Format {
+    /// How `emit` writes this format, or `None` for text, which each command writes itself.
+    fn json(self) -> Option<Json> {
+        match self {
+            Format::Text => None,
+            Format::Json => Some(Json::Document),
+            Format::Ndjson => Some(Json::Lines),
+        }
+    }
+}
+
+/// The JSON output formats, written by `emit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Json {
+    Document,
+    Lines,
+}
+
+/// Where `encode` should hide the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+pub enum Mode {
+    Chunk,
+    Trailing,
+    /// In the least significant bits of the pixels.
+    Lsb,
+}
+
+/// What `encode` should hide.
+#[derive(Debug)]
+pub enum Payload {
+    Message(String),
+    File(PathBuf),
+    Stdin,
+}
+
+/// The passphrase given with --passphrase-file, or else the one given with --passphrase.
+pub fn passphrase(
+    passphrase: Option<String>,
+    passphrase_file: Option<PathBuf>,
+) -> Result<Option<String>> {
+    match passphrase_file {
+        Some(path) => ops::read_passphrase(path).map(Some),
+        None => Ok(passphrase),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn encode(
     input_path: PathBuf,
     chunk_type: String,
-    message: String,
+    payload: Payload,
+    output_path: Option<PathBuf>,
+    mode: Mode,
+    bits: u8,
+    position: String,
+    max_chunk_size: Option<usize>,
+    compress: Option<String>,
+    passphrase: Option<String>,
+    recipient: Option<PathBuf>,
+    sign: Option<PathBuf>,
+    signer_name: String,
+    sign_image: bool,
+) -> Result<()> {
+    // A signature covers chunks only, and embedding in the pixels rewrites IDAT after any
+    // signature over the image would have been made.
+    match mode {
+        _ if sign.is_none() => {}
+        Mode::Chunk => {}
+        Mode::Trailing => return Err(SignatureError::NotInChunk("trailing data").into()),
+        Mode::Lsb => return Err(SignatureError::NotInChunk("the pixels").into()),
+    }
+    let position = ChunkPosition::from_str(&position)?;
+    let compression = compress.as_deref().map(Compression::from_str).transpose()?;
+    let public_key = recipient.map(ops::read_public_key).transpose()?;
+    let encryption = match (&passphrase, &public_key) {
+        (Some(passphrase), _) => Some(Encryption::Passphrase(passphrase)),
+        (None, Some(public_key)) => Some(Encryption::PublicKey(public_key)),
+        (None, None) => None,
+    };
+    let content = match payload {
+        Payload::Message(message) => Content::Message(message.into_bytes()),
+        Payload::File(path) => Content::File(Envelope::from_file(path)?),
+        Payload::Stdin => {
+            let mut data = Vec::new();
+            io::stdin().lock().read_to_end(&mut data)?;
+            let mime_type = envelope::DEFAULT_MIME_TYPE.to_string();
+            Content::File(Envelope::new(None, mime_type, data))
+        }
+    };
+    let data = ops::seal_message(&content, compression, encryption.as_ref())?;
+
+    if mode != Mode::Chunk {
+        let mut png = ops::read_png(&input_path)?;
+        if mode == Mode::Lsb {
+            ops::encode_lsb(&mut png, &chunk_type, &data, bits)?;
+        } else {
+            ops::encode_trailing(&mut png, &chunk_type, &data)?;
+        }
+
+        return match output_path {
+            Some(output_path) => ops::write_png(output_path, &png),
+            None => Ok(()),
+        };
+    }
+
+    let fragments = match max_chunk_size {
+        Some(max_chunk_size) => fragment::split(&data, max_chunk_size)?,
+        None => vec![data],
+    };
+
+    // Signing needs the final layout of the image, so it cannot be done while streaming.
+    if let Some(sign) = sign {
+        let key = ops::read_signing_key(sign)?;
+        let mut png = ops::read_png(&input_path)?;
+        let index = ops::encode_fragments(&mut png, &chunk_type, &fragments, &position)?;
+        ops::sign(&mut png, index, &key, &signer_name, sign_image)?;
+
+        return match output_path {
+            Some(output_path) => ops::write_png(output_path, &png),
+            None => Ok(()),
+        };
+    }
+
+    let reader = open(&input_path)?;
+
+    match output_path {
+        Some(output_path) => replace_file(&output_path, |writer| {
+            ops::encode_fragments_stream(reader, writer, &chunk_type, &fragments, &position)
+        }),
+        None => {
+            ops::encode_fragments_stream(reader, io::sink(), &chunk_type, &fragments, &position)?;
+            Ok(())
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn decode(
+    input_path: PathBuf,
+    chunk_type: String,
     output_path: Option<PathBuf>,
+    all: bool,
+    index: Option<usize>,
+    lenient: bool,
+    passphrase: Option<String>,
+    private_key: Option<PathBuf>,
+    format: Format,
+    mode: Mode,
 ) -> Result<()> {
-    let chunk_type = ChunkType::from_str(&chunk_type)?;
-    let data = message.as_bytes().to_vec();
-    let chunk = Chunk::new(chunk_type, data);
+    if mode == Mode::Lsb && (all || index.is_some() || format != Format::Text) {
+        return Err(LsbError::SingleMessage.into());
+    }
+    let private_key = private_key.map(ops::read_private_key).transpose()?;
+    let decryption = match (&passphrase, &private_key) {
+        (Some(passphrase), _) => Some(Decryption::Passphrase(passphrase)),
+        (None, Some(private_key)) => Some(Decryption::PrivateKey(private_key)),
+        (None, None) => None,
+    };
+
+    if let Some(json) = format.json() {
+        let (chunks, messages) = inspect(&input_path, &chunk_type, lenient)?;
+        let selected = match index {
+            Some(index) => vec![messages
+                .get(index)
+                .map(|message| (index, message))
+                .ok_or(PngError::InvalidMessageIndex(index))?],
+            None if all => messages.iter().enumerate().collect(),
+            None => messages.iter().enumerate().take(1).collect(),
+        };
+
+        let mut reports = Vec::new();
+        for (number, message) in selected {
+            let content = if all {
+                open_listed(&message.payload, decryption.as_ref())?
+            } else {
+                Some(ops::open_payload(&message.payload, decryption.as_ref())?)
+            };
+            reports.push(MessageReport::new(
+                number,
+                message,
+                &chunks,
+                content.as_ref(),
+            ));
+        }
+        return emit(json, "messages", &reports);
+    }
 
-    let mut png = from_file(&input_path)?;
-    png.append_chunk(chunk);
+    if all {
+        for (number, message) in inspect(&input_path, &chunk_type, lenient)?
+            .1
+            .into_iter()
+            .enumerate()
+        {
+            let content = open_listed(&message.payload, decryption.as_ref())?;
+            println!(
+                "Message #{} at chunk {}: {}",
+                number,
+                message.index,
+                summary(&message.payload, content.as_ref())
+            );
+        }
+        return Ok(());
+    }
+
+    let payload = match index {
+        None if mode == Mode::Lsb => ops::payload_lsb(&read(&input_path, lenient)?, &chunk_type)?,
+        Some(index) => {
+            let message = inspect(&input_path, &chunk_type, lenient)?
+                .1
+                .into_iter()
+                .nth(index)
+                .ok_or(PngError::InvalidMessageIndex(index))?;
+            Some(message.payload)
+        }
+        None if lenient => ops::payload(&read(&input_path, lenient)?, &chunk_type)?,
+        None => {
+            let file = map(&input_path)?;
+            ops::payload_ref(&PngRef::try_from(&file[..])?, &chunk_type)?
+        }
+    };
+    let content = payload
+        .map(|payload| ops::open_payload(&payload, decryption.as_ref()))
+        .transpose()?;
+
+    let payload = match content {
+        Some(Content::Message(payload)) => payload,
+        Some(Content::File(envelope)) => {
+            match output_path {
+                Some(output_path) => {
+                    let output_path = extract_path(output_path, &envelope);
+                    fs::write(&output_path, envelope.data())?;
+                    eprintln!("Extracted {} to {}", envelope, output_path.display());
+                }
+                None => io::stdout().lock().write_all(envelope.data())?,
+            }
+            return Ok(());
+        }
+        None => return Ok(()),
+    };
 
-    let bytes = png.as_bytes();
     match output_path {
-        Some(output_path) => Ok(to_file(&output_path, bytes)?),
-        None => Ok(()),
+        Some(output_path) => fs::write(output_path, &payload)?,
+        None => {
+            let message = std::str::from_utf8(&payload)?;
+            println!("Decoded message: '{}'", message);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn remove(input_path: PathBuf, chunk_type: String, all: bool, format: Format) -> Result<()> {
+    // The chunks are described as they were before removal, so look at them first.
+    let reports = match format {
+        Format::Text => Vec::new(),
+        _ => {
+            let (chunks, messages) = inspect(&input_path, &chunk_type, false)?;
+            let count = if all { messages.len() } else { 1 };
+            let mut reports = Vec::new();
+            for (number, message) in messages.iter().enumerate().take(count) {
+                let content = open_listed(&message.payload, None)?;
+                reports.push(MessageReport::new(
+                    number,
+                    message,
+                    &chunks,
+                    content.as_ref(),
+                ));
+            }
+            reports
+        }
+    };
+
+    let reader = open(&input_path)?;
+    let mut summaries = Vec::new();
+
+    // The removed messages are summarized before the file is replaced, so that nothing is
+    // removed if they cannot be.
+    replace_file(&input_path, |writer| {
+        let (chunks, writer) = if all {
+            ops::remove_all_stream(reader, writer, &chunk_type)?
+        } else {
+            ops::remove_stream(reader, writer, &chunk_type)?
+        };
+        if format == Format::Text {
+            let parts = chunks.iter().map(|chunk| chunk.data()).enumerate();
+            for message in ops::collect_messages(parts)? {
+                let content = open_listed(&message.payload, None)?;
+                summaries.push(summary(&message.payload, content.as_ref()));
+            }
+        }
+        Ok(writer)
+    })?;
+
+    if let Some(json) = format.json() {
+        return emit(json, "removed", &reports);
+    }
+
+    for (number, summary) in summaries.iter().enumerate() {
+        if all {
+            println!("Removed message #{}: {}", number, summary);
+        } else {
+            println!("Removed message: {}", summary);
+        }
     }
+
+    Ok(())
 }
 
-pub fn decode(input_path: PathBuf, chunk_type: String) -> Result<()> {
-    let png = from_file(&input_path)?;
+pub fn print(input_path: PathBuf, lenient: bool, format: Format) -> Result<()> {
+    if let Some(json) = format.json() {
+        let reports = if lenient {
+            report::chunk_reports(&read(&input_path, lenient)?)
+        } else {
+            let file = map(&input_path)?;
+            report::chunk_reports_ref(&PngRef::try_from(&file[..])?)?
+        };
+        return emit(json, "chunks", &reports);
+    }
+
+    if lenient {
+        let png = read(&input_path, lenient)?;
+        println!("{}", png);
+        return Ok(());
+    }
 
-    if let Some(chonk) = png.chunk_by_type(&chunk_type) {
-        let data = Chunk::data_as_string(chonk)?;
-        println!("Decoded message: '{}'", data);
+    let file = map(&input_path)?;
+    let png = PngRef::try_from(&file[..])?;
+    for chunk in png.chunks() {
+        println!("{}", chunk?);
     }
+    let trailing_data = png.trailing_data()?;
+    if !trailing_data.is_empty() {
+        println!("{}", TrailingData(trailing_data));
+    }
+    println!();
 
     Ok(())
 }
 
-pub fn remove(input_path: PathBuf, chunk_type: String) -> Result<()> {
-    let mut png = from_file(&input_path)?;
-    let removed_chunk = png.remove_chunk(&chunk_type)?;
+pub fn verify(input_path: PathBuf, chunk_type: String, public_key: Option<PathBuf>) -> Result<()> {
+    let png = ops::read_png(&input_path)?;
+    let verification = ops::verify(&png, &chunk_type)?;
+    println!("{}", verification);
 
-    println!("Removed message: '{}'", removed_chunk.data_as_string()?);
+    if let Some(public_key) = public_key {
+        if ops::read_verifying_key(public_key)? != verification.signer {
+            return Err(SignatureError::UntrustedSigner(verification.signer.bytes()).into());
+        }
+    }
 
-    let bytes = png.as_bytes();
-    to_file(&input_path, bytes)?;
+    if !verification.is_valid() {
+        return Err(SignatureError::Invalid.into());
+    }
 
     Ok(())
 }
 
-pub fn print(input_path: PathBuf) -> Result<()> {
-    let png = from_file(&input_path)?;
-    println!("{}", png);
+pub fn validate(input_path: PathBuf, lenient: bool) -> Result<()> {
+    let violations = read(&input_path, lenient)?.validate();
+    for violation in &violations {
+        println!("{}", violation);
+    }
+
+    if !violations.is_empty() {
+        return Err(PngError::InvalidStructure(violations.len()).into());
+    }
 
+    println!("Structure is valid");
     Ok(())
 }
 
-fn from_file<P: AsRef<Path>>(path: &P) -> Result<Png> {
-    let file_contents = fs::read(path)?;
-    let png = Png::try_from(file_contents.as_slice())?;
+pub fn strip(input_path: PathBuf) -> Result<()> {
+    let mut png = ops::read_png(&input_path)?;
+    let trailing_data = png.strip_trailing_data();
+    if trailing_data.is_empty() {
+        println!("No trailing data to strip");
+        return Ok(());
+    }
+
+    write_in_place(&input_path, &png)?;
+    println!("Stripped {} bytes of trailing data", trailing_data.len());
+    Ok(())
+}
+
+pub fn capacity(input_path: PathBuf) -> Result<()> {
+    let png = ops::read_png(&input_path)?;
+    for embedding in Embedding::ALL {
+        match png.capacity(embedding) {
+            Ok(Some(capacity)) if embedding == Embedding::Chunk => {
+                println!("{}: {} bytes per chunk", embedding, capacity)
+            }
+            Ok(Some(capacity)) => println!("{}: {} bytes", embedding, capacity),
+            Ok(None) => println!("{}: unlimited", embedding),
+            Err(err) => println!("{}: none, {}", embedding, err),
+        }
+    }
+    Ok(())
+}
+
+pub fn text_add(
+    input_path: PathBuf,
+    keyword: String,
+    text: String,
+    compress: bool,
+    international: bool,
+    language: Option<String>,
+    translated_keyword: Option<String>,
+) -> Result<()> {
+    let entry = match (international, compress) {
+        (true, _) => TextEntry::international(
+            &keyword,
+            &text,
+            language.as_deref().unwrap_or(""),
+            translated_keyword.as_deref().unwrap_or(""),
+            compress,
+        ),
+        (false, true) => TextEntry::compressed(&keyword, &text),
+        (false, false) => TextEntry::new(&keyword, &text),
+    };
+
+    let mut png = ops::read_png(&input_path)?;
+    text::add(&mut png, &entry)?;
+    write_in_place(&input_path, &png)
+}
+
+pub fn text_list(input_path: PathBuf) -> Result<()> {
+    for entry in TextEntry::find_all(&ops::read_png(&input_path)?) {
+        match entry {
+            Ok(entry) => println!("{}", entry),
+            Err(err) => eprintln!("warning: skipped text chunk: {}", err),
+        }
+    }
+    Ok(())
+}
+
+pub fn text_remove(input_path: PathBuf, keyword: String) -> Result<()> {
+    let mut png = ops::read_png(&input_path)?;
+    let removed = text::remove(&mut png, &keyword)?;
+    write_in_place(&input_path, &png)?;
+
+    for entry in removed {
+        println!("Removed {}", entry);
+    }
+    Ok(())
+}
+
+pub fn keygen(output_path: PathBuf, signing: bool) -> Result<()> {
+    let (private_key, public_key) = if signing {
+        let key = SigningKey::generate();
+        (key.to_string(), key.verifying_key().to_string())
+    } else {
+        let key = PrivateKey::generate();
+        (key.to_string(), key.public_key().to_string())
+    };
+
+    let mut public_path = output_path.as_os_str().to_owned();
+    public_path.push(".pub");
+    let public_path = PathBuf::from(public_path);
+
+    write_private(&output_path, format!("{}\n", private_key).as_bytes())?;
+    fs::write(&public_path, format!("{}\n", public_key))?;
+
+    println!("Wrote private key to {}", output_path.display());
+    println!("Wrote public key to {}", public_path.display());
+
+    Ok(())
+}
+
+fn read(input_path: &Path, lenient: bool) -> Result<Png> {
+    if !lenient {
+        return ops::read_png(input_path);
+    }
+
+    let (png, diagnostics) = ops::read_png_lenient(input_path)?;
+    for diagnostic in diagnostics {
+        eprintln!("warning: {}", diagnostic);
+    }
     Ok(png)
 }
 
-fn to_file<P: AsRef<Path>>(path: &P, contents: Vec<u8>) -> Result<()> {
-    fs::write(path, contents)?;
+/// Describes every chunk of the file and collects the messages stored under `chunk_type`.
+fn inspect(
+    input_path: &Path,
+    chunk_type: &str,
+    lenient: bool,
+) -> Result<(Vec<ChunkReport>, Vec<StoredMessage>)> {
+    if lenient {
+        let png = read(input_path, lenient)?;
+        return Ok((
+            report::chunk_reports(&png),
+            ops::payloads(&png, chunk_type)?,
+        ));
+    }
+
+    let file = map(input_path)?;
+    let png = PngRef::try_from(&file[..])?;
+    Ok((
+        report::chunk_reports_ref(&png)?,
+        ops::payloads_ref(&png, chunk_type)?,
+    ))
+}
+
+/// Writes `records` to standard output, either as one JSON document with them listed under
+/// `key` or as one line each. Callers write text output themselves.
+fn emit<T: Serialize>(json: Json, key: &str, records: &[T]) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    match json {
+        Json::Document => {
+            let document = BTreeMap::from([(key, records)]);
+            serde_json::to_writer_pretty(&mut stdout, &document).map_err(io::Error::from)?;
+            writeln!(stdout)?;
+        }
+        Json::Lines => {
+            for record in records {
+                serde_json::to_writer(&mut stdout, record).map_err(io::Error::from)?;
+                writeln!(stdout)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Opens one of several messages being listed, which need not all be encrypted. Encrypted
+/// messages are left as they are, giving `None`, when there is no key for them.
+fn open_listed(data: &[u8], decryption: Option<&Decryption>) -> Result<Option<Content>> {
+    if decryption.is_none() && sealed::is_encrypted(data) {
+        return Ok(None);
+    }
+    ops::open_payload(data, decryption).map(Some)
+}
+
+/// Describes a payload in a line, given what `open_listed` found in it. Messages that are not
+/// text are shown as the hex of their first few bytes.
+fn summary(payload: &[u8], content: Option<&Content>) -> String {
+    const PREVIEW_SIZE: usize = 16;
+
+    let message = match content {
+        None => return format!("encrypted ({} bytes)", payload.len()),
+        Some(Content::File(envelope)) => return format!("file {}", envelope),
+        Some(Content::Message(message)) => message,
+    };
+    if let Ok(text) = std::str::from_utf8(message) {
+        return format!("'{}'", text);
+    }
+
+    let preview: String = message
+        .iter()
+        .take(PREVIEW_SIZE)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let ellipsis = if message.len() > PREVIEW_SIZE {
+        "..."
+    } else {
+        ""
+    };
+    format!("binary ({} bytes): {}{}", message.len(), preview, ellipsis)
+}
+
+/// Where to write an extracted file. A directory gets the file's original name appended, keeping
+/// only its final component so a crafted name cannot escape the directory.
+fn extract_path(output_path: PathBuf, envelope: &Envelope) -> PathBuf {
+    if !output_path.is_dir() {
+        return output_path;
+    }
+
+    let filename = envelope
+        .filename()
+        .and_then(|name| Path::new(name).file_name())
+        .unwrap_or_else(|| "message.bin".as_ref());
+    output_path.join(filename)
+}
+
+fn map(path: &Path) -> Result<Mmap> {
+    let file = File::open(path)?;
+    // Safety: the mapping is only read, and png-msg does not modify input files while
+    // decoding or printing them.
+    Ok(unsafe { Mmap::map(&file)? })
+}
+
+fn open(path: &Path) -> Result<BufReader<File>> {
+    Ok(BufReader::new(File::open(path)?))
+}
+
+/// Writes `path` through a temporary sibling file that is renamed into place once `write`
+/// succeeds, so `path` may also be the file being read from.
+fn replace_file<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(BufWriter<File>) -> Result<BufWriter<File>>,
+{
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    let result = File::create(&temp_path)
+        .map_err(Into::into)
+        .and_then(|file| write(BufWriter::new(file)))
+        .and_then(|writer| writer.into_inner().map_err(|err| err.into_error().into()));
+
+    match result {
+        Ok(_) => Ok(fs::rename(&temp_path, path)?),
+        Err(err) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(err)
+        }
+    }
+}
+
+/// Replaces the file at `path` with `png`.
+fn write_in_place(path: &Path, png: &Png) -> Result<()> {
+    replace_file(path, |mut writer| {
+        writer.write_all(&png.as_bytes())?;
+        Ok(writer)
+    })
+}
+
+/// Creates `path` so that only the current user can read it.
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(contents)?;
     Ok(())
 }
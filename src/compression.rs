@@ -0,0 +1,273 @@
+use crate::Result;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+/// Marks chunk data produced by `compress`.
+pub const MAGIC: [u8; 4] = *b"PMCZ";
+
+const ALGORITHM_DEFLATE: u8 = 1;
+const ALGORITHM_ZSTD: u8 = 2;
+
+const ZSTD_LEVEL: i32 = 19;
+
+/// Size of the header that precedes the compressed data: magic, algorithm and original size.
+pub const HEADER_SIZE: usize = MAGIC.len() + 1 + 4;
+
+/// How a message should be compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Deflate,
+    Zstd,
+}
+
+impl Compression {
+    fn id(&self) -> u8 {
+        match self {
+            Compression::Deflate => ALGORITHM_DEFLATE,
+            Compression::Zstd => ALGORITHM_ZSTD,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Compression> {
+        match id {
+            ALGORITHM_DEFLATE => Some(Compression::Deflate),
+            ALGORITHM_ZSTD => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "deflate" => Ok(Compression::Deflate),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(CompressionError::InvalidAlgorithm(s.to_string()).into()),
+        }
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::Deflate => write!(f, "deflate"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+/// What the header of a compressed payload says about it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionInfo {
+    pub algorithm: Compression,
+    pub original_size: usize,
+    pub compressed_size: usize,
+}
+
+/// Compresses `data` with `algorithm`, prefixing it with a header that `decompress` reads back.
+pub fn compress(data: &[u8], algorithm: Compression) -> Result<Vec<u8>> {
+    let original_size =
+        u32::try_from(data.len()).map_err(|_| CompressionError::TooLarge(data.len()))?;
+
+    let mut payload = MAGIC.to_vec();
+    payload.push(algorithm.id());
+    payload.extend_from_slice(&original_size.to_be_bytes());
+
+    match algorithm {
+        Compression::Deflate => {
+            let mut encoder = DeflateEncoder::new(payload, flate2::Compression::best());
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        }
+        Compression::Zstd => {
+            zstd::stream::copy_encode(data, &mut payload, ZSTD_LEVEL)?;
+            Ok(payload)
+        }
+    }
+}
+
+/// Reverses `compress`. The output is never allowed to grow past the size recorded in the
+/// header, and no room is set aside for it up front, so a hostile payload cannot make it
+/// allocate more than it actually decompresses to.
+pub fn decompress(payload: &[u8]) -> Result<Vec<u8>> {
+    if payload.len() < HEADER_SIZE || payload[..MAGIC.len()] != MAGIC {
+        return Err(CompressionError::NotCompressed.into());
+    }
+    let info = info(payload).ok_or(CompressionError::UnsupportedAlgorithm(payload[MAGIC.len()]))?;
+
+    let body = &payload[HEADER_SIZE..];
+    let limit = info.original_size as u64 + 1;
+    let mut data = Vec::new();
+    let result = match info.algorithm {
+        Compression::Deflate => DeflateDecoder::new(body).take(limit).read_to_end(&mut data),
+        Compression::Zstd => zstd::stream::Decoder::new(body)
+            .and_then(|decoder| decoder.take(limit).read_to_end(&mut data)),
+    };
+    result.map_err(|_| CompressionError::Corrupt)?;
+
+    if data.len() != info.original_size {
+        return Err(CompressionError::SizeMismatch {
+            expected: info.original_size,
+            actual: data.len(),
+        }
+        .into());
+    }
+
+    Ok(data)
+}
+
+/// Reads the header of a compressed payload, or returns `None` if `data` was not produced by
+/// `compress`.
+pub fn info(data: &[u8]) -> Option<CompressionInfo> {
+    if data.len() < HEADER_SIZE || data[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+
+    let algorithm = Compression::from_id(data[MAGIC.len()])?;
+    let original_size = u32::from_be_bytes(data[MAGIC.len() + 1..HEADER_SIZE].try_into().unwrap());
+
+    Some(CompressionInfo {
+        algorithm,
+        original_size: original_size as usize,
+        compressed_size: data.len(),
+    })
+}
+
+/// Returns whether `data` starts with a header written by `compress`.
+pub fn is_compressed(data: &[u8]) -> bool {
+    info(data).is_some()
+}
+
+#[derive(Debug)]
+pub enum CompressionError {
+    NotCompressed,
+    InvalidAlgorithm(String),
+    UnsupportedAlgorithm(u8),
+    TooLarge(usize),
+    Corrupt,
+    SizeMismatch { expected: usize, actual: usize },
+}
+
+impl std::error::Error for CompressionError {}
+
+impl Display for CompressionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressionError::NotCompressed => write!(f, "chunk data is not compressed"),
+            CompressionError::InvalidAlgorithm(name) => write!(
+                f,
+                "unknown compression algorithm '{}', expected deflate or zstd",
+                name
+            ),
+            CompressionError::UnsupportedAlgorithm(id) => {
+                write!(f, "unsupported compression algorithm {}", id)
+            }
+            CompressionError::TooLarge(size) => {
+                write!(f, "message of {} bytes is too large to compress", size)
+            }
+            CompressionError::Corrupt => write!(f, "compressed message is corrupt"),
+            CompressionError::SizeMismatch { expected, actual } => write!(
+                f,
+                "compressed message should be {} bytes but decompressed to {}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn testing_message() -> Vec<u8> {
+        b"{\"level\":\"info\",\"msg\":\"hello\"}\n".repeat(50)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let message = testing_message();
+        for algorithm in [Compression::Deflate, Compression::Zstd] {
+            let payload = compress(&message, algorithm).unwrap();
+            assert!(payload.len() < message.len());
+            assert_eq!(decompress(&payload).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_info() {
+        let message = testing_message();
+        let payload = compress(&message, Compression::Zstd).unwrap();
+
+        let info = info(&payload).unwrap();
+        assert_eq!(info.algorithm, Compression::Zstd);
+        assert_eq!(info.original_size, message.len());
+        assert_eq!(info.compressed_size, payload.len());
+
+        assert!(is_compressed(&payload));
+        assert!(!is_compressed(&message));
+    }
+
+    #[test]
+    fn test_empty_message() {
+        let payload = compress(b"", Compression::Deflate).unwrap();
+        assert_eq!(decompress(&payload).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_not_compressed() {
+        assert!(matches!(
+            decompress(b"Hello"),
+            Err(Error::Compression(CompressionError::NotCompressed))
+        ));
+    }
+
+    #[test]
+    fn test_size_mismatch() {
+        let message = testing_message();
+        let mut payload = compress(&message, Compression::Deflate).unwrap();
+        // Claim the message is smaller than it is.
+        payload[MAGIC.len() + 1..HEADER_SIZE].copy_from_slice(&10u32.to_be_bytes());
+
+        assert!(matches!(
+            decompress(&payload),
+            Err(Error::Compression(CompressionError::SizeMismatch {
+                expected: 10,
+                actual: 11,
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_claimed_size_too_large() {
+        let mut payload = compress(b"Hello", Compression::Zstd).unwrap();
+        payload[MAGIC.len() + 1..HEADER_SIZE].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        assert!(matches!(
+            decompress(&payload),
+            Err(Error::Compression(CompressionError::SizeMismatch {
+                actual: 5,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_payload() {
+        let mut payload = compress(&testing_message(), Compression::Zstd).unwrap();
+        payload.truncate(HEADER_SIZE + 4);
+        assert!(decompress(&payload).is_err());
+    }
+
+    #[test]
+    fn test_algorithm_from_str() {
+        assert_eq!(Compression::from_str("zstd").unwrap(), Compression::Zstd);
+        assert_eq!(Compression::Deflate.to_string(), "deflate");
+        assert!(Compression::from_str("lzma").is_err());
+    }
+}
@@ -0,0 +1,528 @@
+use crate::Result;
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use x25519_dalek::{EphemeralSecret, StaticSecret};
+
+/// Marks chunk data produced by this module.
+pub const MAGIC: [u8; 4] = *b"PMSG";
+
+pub const SCHEME_PASSPHRASE: u8 = 1;
+pub const SCHEME_PUBLIC_KEY: u8 = 2;
+
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 24;
+const KEY_SIZE: usize = 32;
+
+/// Size of the header that precedes the ciphertext of a passphrase-encrypted payload.
+const PASSPHRASE_HEADER_SIZE: usize = MAGIC.len() + 1 + SALT_SIZE + NONCE_SIZE;
+
+/// Size of the header that precedes the ciphertext of a payload encrypted for a public key.
+const PUBLIC_KEY_HEADER_SIZE: usize = MAGIC.len() + 1 + KEY_SIZE + NONCE_SIZE;
+
+/// Size of the authentication tag that follows the ciphertext.
+const TAG_SIZE: usize = 16;
+
+const PUBLIC_KEY_INFO: &[u8] = b"png-msg x25519 xchacha20poly1305";
+
+/// How a message should be encrypted.
+pub enum Encryption<'a> {
+    Passphrase(&'a str),
+    PublicKey(&'a PublicKey),
+}
+
+impl Encryption<'_> {
+    /// The scheme byte of the header `encrypt_with` writes.
+    pub fn scheme(&self) -> u8 {
+        match self {
+            Encryption::Passphrase(_) => SCHEME_PASSPHRASE,
+            Encryption::PublicKey(_) => SCHEME_PUBLIC_KEY,
+        }
+    }
+}
+
+/// How a message should be decrypted.
+pub enum Decryption<'a> {
+    Passphrase(&'a str),
+    PrivateKey(&'a PrivateKey),
+}
+
+/// An X25519 public key that messages can be encrypted for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicKey(x25519_dalek::PublicKey);
+
+impl PublicKey {
+    pub fn bytes(&self) -> [u8; KEY_SIZE] {
+        self.0.to_bytes()
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(PublicKey(x25519_dalek::PublicKey::from(decode_key(s)?)))
+    }
+}
+
+impl Display for PublicKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_hex(f, &self.bytes())
+    }
+}
+
+/// The X25519 private key matching a `PublicKey`.
+pub struct PrivateKey(StaticSecret);
+
+impl PrivateKey {
+    pub fn generate() -> PrivateKey {
+        PrivateKey(StaticSecret::random_from_rng(OsRng))
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(x25519_dalek::PublicKey::from(&self.0))
+    }
+
+    pub fn bytes(&self) -> [u8; KEY_SIZE] {
+        self.0.to_bytes()
+    }
+}
+
+impl FromStr for PrivateKey {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(PrivateKey(StaticSecret::from(decode_key(s)?)))
+    }
+}
+
+impl Display for PrivateKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_hex(f, &self.bytes())
+    }
+}
+
+/// Encrypts `plaintext` as described by `encryption`.
+pub fn encrypt_with(plaintext: &[u8], encryption: &Encryption) -> Result<Vec<u8>> {
+    match encryption {
+        Encryption::Passphrase(passphrase) => encrypt(plaintext, passphrase),
+        Encryption::PublicKey(public_key) => encrypt_for(plaintext, public_key),
+    }
+}
+
+/// Decrypts `payload` as described by `decryption`.
+pub fn decrypt_with(payload: &[u8], decryption: &Decryption) -> Result<Vec<u8>> {
+    match decryption {
+        Decryption::Passphrase(passphrase) => decrypt(payload, passphrase),
+        Decryption::PrivateKey(private_key) => decrypt_as(payload, private_key),
+    }
+}
+
+/// Encrypts `plaintext` with a key derived from `passphrase` using Argon2id, sealing it with
+/// XChaCha20-Poly1305.
+///
+/// The result is laid out as `MAGIC | scheme | salt | nonce | ciphertext`, and the header
+/// before the ciphertext is authenticated along with it.
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+
+    let mut header = Vec::with_capacity(PASSPHRASE_HEADER_SIZE);
+    header.extend_from_slice(&MAGIC);
+    header.push(SCHEME_PASSPHRASE);
+    header.extend_from_slice(&salt);
+
+    seal(header, &derive_key(passphrase, &salt)?, plaintext)
+}
+
+/// Reverses `encrypt`. Fails with `CryptoError::DecryptionFailed` when the passphrase is wrong
+/// or the payload has been modified.
+pub fn decrypt(payload: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    check_scheme(payload, SCHEME_PASSPHRASE, PASSPHRASE_HEADER_SIZE)?;
+
+    let salt = &payload[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_SIZE];
+    open(
+        payload,
+        PASSPHRASE_HEADER_SIZE,
+        &derive_key(passphrase, salt)?,
+    )
+}
+
+/// Encrypts `plaintext` so that only the holder of the private key for `public_key` can read
+/// it. An ephemeral X25519 key agreement with `public_key` is run through HKDF-SHA256 to get
+/// the XChaCha20-Poly1305 key.
+///
+/// The result is laid out as `MAGIC | scheme | ephemeral public key | nonce | ciphertext`.
+pub fn encrypt_for(plaintext: &[u8], public_key: &PublicKey) -> Result<Vec<u8>> {
+    let ephemeral_secret = EphemeralSecret::random_from_rng(OsRng);
+    let ephemeral_public = x25519_dalek::PublicKey::from(&ephemeral_secret);
+    let shared_secret = ephemeral_secret.diffie_hellman(&public_key.0);
+
+    let mut header = Vec::with_capacity(PUBLIC_KEY_HEADER_SIZE);
+    header.extend_from_slice(&MAGIC);
+    header.push(SCHEME_PUBLIC_KEY);
+    header.extend_from_slice(ephemeral_public.as_bytes());
+
+    let key = expand_key(
+        shared_secret.as_bytes(),
+        ephemeral_public.as_bytes(),
+        public_key,
+    )?;
+    seal(header, &key, plaintext)
+}
+
+/// Reverses `encrypt_for`. Fails with `CryptoError::DecryptionFailed` when the message was
+/// encrypted for a different key or the payload has been modified.
+pub fn decrypt_as(payload: &[u8], private_key: &PrivateKey) -> Result<Vec<u8>> {
+    check_scheme(payload, SCHEME_PUBLIC_KEY, PUBLIC_KEY_HEADER_SIZE)?;
+
+    let ephemeral_public: [u8; KEY_SIZE] = payload[MAGIC.len() + 1..MAGIC.len() + 1 + KEY_SIZE]
+        .try_into()
+        .unwrap();
+    let shared_secret = private_key
+        .0
+        .diffie_hellman(&x25519_dalek::PublicKey::from(ephemeral_public));
+
+    let key = expand_key(
+        shared_secret.as_bytes(),
+        &ephemeral_public,
+        &private_key.public_key(),
+    )?;
+    open(payload, PUBLIC_KEY_HEADER_SIZE, &key)
+}
+
+/// Returns the encryption scheme of `data`, or `None` if it was not produced by this module.
+pub fn scheme(data: &[u8]) -> Option<u8> {
+    if data.len() <= MAGIC.len() || data[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+
+    match data[MAGIC.len()] {
+        scheme @ (SCHEME_PASSPHRASE | SCHEME_PUBLIC_KEY) => Some(scheme),
+        _ => None,
+    }
+}
+
+/// Number of bytes encrypting with `scheme` adds to the plaintext: the header and the
+/// authentication tag. `None` if the scheme is unknown.
+pub fn overhead(scheme: u8) -> Option<usize> {
+    match scheme {
+        SCHEME_PASSPHRASE => Some(PASSPHRASE_HEADER_SIZE + TAG_SIZE),
+        SCHEME_PUBLIC_KEY => Some(PUBLIC_KEY_HEADER_SIZE + TAG_SIZE),
+        _ => None,
+    }
+}
+
+/// Returns whether `data` starts with a header written by one of the encryption functions.
+pub fn is_encrypted(data: &[u8]) -> bool {
+    scheme(data).is_some()
+}
+
+/// Appends a random nonce to `header` and then the ciphertext of `plaintext`, authenticating
+/// the header along with it.
+fn seal(mut header: Vec<u8>, key: &Key, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    header.extend_from_slice(&nonce);
+
+    let ciphertext = XChaCha20Poly1305::new(key)
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad: &header,
+            },
+        )
+        .map_err(|_| CryptoError::EncryptionFailed)?;
+
+    header.extend(ciphertext);
+    Ok(header)
+}
+
+/// Reverses `seal` for a payload whose header, ending in the nonce, is `header_size` bytes.
+fn open(payload: &[u8], header_size: usize, key: &Key) -> Result<Vec<u8>> {
+    let (header, ciphertext) = payload.split_at(header_size);
+    let nonce = XNonce::from_slice(&header[header_size - NONCE_SIZE..]);
+
+    let plaintext = XChaCha20Poly1305::new(key)
+        .decrypt(
+            nonce,
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| CryptoError::DecryptionFailed)?;
+
+    Ok(plaintext)
+}
+
+fn check_scheme(payload: &[u8], expected: u8, header_size: usize) -> Result<()> {
+    if payload.len() <= MAGIC.len() || payload[..MAGIC.len()] != MAGIC {
+        return Err(CryptoError::NotEncrypted.into());
+    }
+
+    match payload[MAGIC.len()] {
+        scheme if scheme == expected => {}
+        scheme @ (SCHEME_PASSPHRASE | SCHEME_PUBLIC_KEY) => {
+            return Err(CryptoError::SchemeMismatch(scheme).into())
+        }
+        scheme => return Err(CryptoError::UnsupportedScheme(scheme).into()),
+    }
+
+    if payload.len() < header_size {
+        return Err(CryptoError::NotEncrypted.into());
+    }
+
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key> {
+    let mut key = [0; KEY_SIZE];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| CryptoError::KeyDerivationFailed)?;
+    Ok(Key::from(key))
+}
+
+/// Derives a symmetric key from an X25519 shared secret, binding in both public keys.
+fn expand_key(
+    shared_secret: &[u8],
+    ephemeral_public: &[u8; KEY_SIZE],
+    recipient: &PublicKey,
+) -> Result<Key> {
+    let mut salt = [0; 2 * KEY_SIZE];
+    salt[..KEY_SIZE].copy_from_slice(ephemeral_public);
+    salt[KEY_SIZE..].copy_from_slice(&recipient.bytes());
+
+    let mut key = [0; KEY_SIZE];
+    Hkdf::<Sha256>::new(Some(&salt), shared_secret)
+        .expand(PUBLIC_KEY_INFO, &mut key)
+        .map_err(|_| CryptoError::KeyDerivationFailed)?;
+    Ok(Key::from(key))
+}
+
+pub(crate) fn decode_key(s: &str) -> Result<[u8; KEY_SIZE]> {
+    let s = s.trim();
+    if s.len() != 2 * KEY_SIZE || !s.is_ascii() {
+        return Err(CryptoError::InvalidKey.into());
+    }
+
+    let mut key = [0; KEY_SIZE];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte =
+            u8::from_str_radix(&s[2 * i..2 * i + 2], 16).map_err(|_| CryptoError::InvalidKey)?;
+    }
+    Ok(key)
+}
+
+pub(crate) fn write_hex(f: &mut Formatter<'_>, bytes: &[u8]) -> std::fmt::Result {
+    for byte in bytes {
+        write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
+fn scheme_name(scheme: u8) -> &'static str {
+    match scheme {
+        SCHEME_PASSPHRASE => "a passphrase",
+        SCHEME_PUBLIC_KEY => "a public key",
+        _ => "an unknown scheme",
+    }
+}
+
+#[derive(Debug)]
+pub enum CryptoError {
+    NotEncrypted,
+    PassphraseRequired,
+    PrivateKeyRequired,
+    UnsupportedScheme(u8),
+    SchemeMismatch(u8),
+    InvalidKey,
+    KeyDerivationFailed,
+    EncryptionFailed,
+    DecryptionFailed,
+}
+
+impl std::error::Error for CryptoError {}
+
+impl Display for CryptoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CryptoError::NotEncrypted => write!(f, "chunk data is not an encrypted payload"),
+            CryptoError::PassphraseRequired => {
+                write!(f, "message is encrypted, a passphrase is required")
+            }
+            CryptoError::PrivateKeyRequired => {
+                write!(
+                    f,
+                    "message is encrypted for a recipient, a private key is required"
+                )
+            }
+            CryptoError::UnsupportedScheme(scheme) => {
+                write!(f, "unsupported encryption scheme {}", scheme)
+            }
+            CryptoError::SchemeMismatch(scheme) => write!(
+                f,
+                "message was encrypted with {}, which does not match the key given",
+                scheme_name(*scheme)
+            ),
+            CryptoError::InvalidKey => write!(f, "key must be {} hex-encoded bytes", KEY_SIZE),
+            CryptoError::KeyDerivationFailed => write!(f, "could not derive key from passphrase"),
+            CryptoError::EncryptionFailed => write!(f, "encryption failed"),
+            CryptoError::DecryptionFailed => write!(
+                f,
+                "decryption failed, the key is wrong or the message was tampered with"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn test_round_trip() {
+        let payload = encrypt(b"Hello", "hunter2").unwrap();
+        assert!(is_encrypted(&payload));
+        assert_ne!(&payload[PASSPHRASE_HEADER_SIZE..], b"Hello");
+        assert_eq!(decrypt(&payload, "hunter2").unwrap(), b"Hello");
+    }
+
+    #[test]
+    fn test_overhead() {
+        let public_key = PrivateKey::generate().public_key();
+        for encryption in [
+            Encryption::Passphrase("hunter2"),
+            Encryption::PublicKey(&public_key),
+        ] {
+            let payload = encrypt_with(b"Hello", &encryption).unwrap();
+            assert_eq!(
+                overhead(encryption.scheme()),
+                Some(payload.len() - b"Hello".len())
+            );
+        }
+        assert_eq!(overhead(3), None);
+    }
+
+    #[test]
+    fn test_random_salt_and_nonce() {
+        let first = encrypt(b"Hello", "hunter2").unwrap();
+        let second = encrypt(b"Hello", "hunter2").unwrap();
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let payload = encrypt(b"Hello", "hunter2").unwrap();
+        assert!(matches!(
+            decrypt(&payload, "hunter3"),
+            Err(Error::Crypto(CryptoError::DecryptionFailed))
+        ));
+    }
+
+    #[test]
+    fn test_tampered_payload() {
+        let payload = encrypt(b"Hello", "hunter2").unwrap();
+
+        // Flip one bit in the salt, the nonce and the ciphertext in turn.
+        for index in [
+            MAGIC.len() + 1,
+            PASSPHRASE_HEADER_SIZE - 1,
+            payload.len() - 1,
+        ] {
+            let mut tampered = payload.clone();
+            tampered[index] ^= 1;
+            assert!(matches!(
+                decrypt(&tampered, "hunter2"),
+                Err(Error::Crypto(CryptoError::DecryptionFailed))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_not_encrypted() {
+        assert!(!is_encrypted(b"Hello"));
+        assert!(matches!(
+            decrypt(b"Hello", "hunter2"),
+            Err(Error::Crypto(CryptoError::NotEncrypted))
+        ));
+    }
+
+    #[test]
+    fn test_public_key_round_trip() {
+        let private_key = PrivateKey::generate();
+        let payload = encrypt_for(b"Hello", &private_key.public_key()).unwrap();
+
+        assert_eq!(scheme(&payload), Some(SCHEME_PUBLIC_KEY));
+        assert_eq!(decrypt_as(&payload, &private_key).unwrap(), b"Hello");
+    }
+
+    #[test]
+    fn test_public_key_wrong_recipient() {
+        let payload = encrypt_for(b"Hello", &PrivateKey::generate().public_key()).unwrap();
+        assert!(matches!(
+            decrypt_as(&payload, &PrivateKey::generate()),
+            Err(Error::Crypto(CryptoError::DecryptionFailed))
+        ));
+    }
+
+    #[test]
+    fn test_public_key_tampered_payload() {
+        let private_key = PrivateKey::generate();
+        let payload = encrypt_for(b"Hello", &private_key.public_key()).unwrap();
+
+        // Flip one bit in the ephemeral key, the nonce and the ciphertext in turn.
+        for index in [
+            MAGIC.len() + 1,
+            PUBLIC_KEY_HEADER_SIZE - 1,
+            payload.len() - 1,
+        ] {
+            let mut tampered = payload.clone();
+            tampered[index] ^= 1;
+            assert!(decrypt_as(&tampered, &private_key).is_err());
+        }
+    }
+
+    #[test]
+    fn test_scheme_mismatch() {
+        let private_key = PrivateKey::generate();
+        let payload = encrypt_for(b"Hello", &private_key.public_key()).unwrap();
+        assert!(matches!(
+            decrypt(&payload, "hunter2"),
+            Err(Error::Crypto(CryptoError::SchemeMismatch(
+                SCHEME_PUBLIC_KEY
+            )))
+        ));
+
+        let payload = encrypt(b"Hello", "hunter2").unwrap();
+        assert!(matches!(
+            decrypt_as(&payload, &private_key),
+            Err(Error::Crypto(CryptoError::SchemeMismatch(
+                SCHEME_PASSPHRASE
+            )))
+        ));
+    }
+
+    #[test]
+    fn test_key_text_round_trip() {
+        let private_key = PrivateKey::generate();
+        let public_key = private_key.public_key();
+
+        let parsed = PrivateKey::from_str(&private_key.to_string()).unwrap();
+        assert_eq!(parsed.bytes(), private_key.bytes());
+        assert_eq!(
+            PublicKey::from_str(&format!("{}\n", public_key)).unwrap(),
+            public_key
+        );
+
+        assert!(PublicKey::from_str("abcd").is_err());
+        assert!(PublicKey::from_str(&"zz".repeat(KEY_SIZE)).is_err());
+    }
+}
@@ -0,0 +1,292 @@
+use crate::Result;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+/// Marks chunk data produced by `Envelope::as_bytes`.
+pub const MAGIC: [u8; 4] = *b"PMFE";
+
+const VERSION: u8 = 1;
+
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// MIME types for the file extensions we are likely to be asked to hide.
+const MIME_TYPES: &[(&str, &str)] = &[
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("html", "text/html"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+];
+
+/// A file wrapped with enough metadata to extract it again byte-for-byte.
+///
+/// Serialized as `magic | version | name length (u16) | name | MIME type length (u8) |
+/// MIME type | size (u64) | data`, with lengths and size in big-endian order. An empty name
+/// means the data did not come from a named file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    filename: Option<String>,
+    mime_type: String,
+    data: Vec<u8>,
+}
+
+impl Envelope {
+    pub fn new(filename: Option<String>, mime_type: String, data: Vec<u8>) -> Envelope {
+        Envelope {
+            filename,
+            mime_type,
+            data,
+        }
+    }
+
+    /// Reads the file at `path`, recording its name and a MIME type guessed from its extension.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Envelope> {
+        let path = path.as_ref();
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        let mime_type = guess_mime_type(path).to_string();
+        Ok(Envelope::new(filename, mime_type, fs::read(path)?))
+    }
+
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    pub fn mime_type(&self) -> &str {
+        &self.mime_type
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        let filename = self.filename.as_deref().unwrap_or("");
+        let name_length = u16::try_from(filename.len())
+            .map_err(|_| EnvelopeError::FieldTooLong("filename", filename.len()))?;
+        let mime_length = u8::try_from(self.mime_type.len())
+            .map_err(|_| EnvelopeError::FieldTooLong("MIME type", self.mime_type.len()))?;
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&name_length.to_be_bytes());
+        bytes.extend_from_slice(filename.as_bytes());
+        bytes.push(mime_length);
+        bytes.extend_from_slice(self.mime_type.as_bytes());
+        bytes.extend_from_slice(&(self.data.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&self.data);
+        Ok(bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for Envelope {
+    type Error = crate::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self> {
+        if !is_envelope(value) {
+            return Err(EnvelopeError::NotAnEnvelope.into());
+        }
+
+        let mut reader = FieldReader {
+            value,
+            offset: MAGIC.len(),
+        };
+
+        let version = reader.take(1)?[0];
+        if version != VERSION {
+            return Err(EnvelopeError::UnsupportedVersion(version).into());
+        }
+
+        let name_length = u16::from_be_bytes(reader.take(2)?.try_into().unwrap()) as usize;
+        let filename = std::str::from_utf8(reader.take(name_length)?)?;
+        let mime_length = reader.take(1)?[0] as usize;
+        let mime_type = std::str::from_utf8(reader.take(mime_length)?)?;
+        let size = u64::from_be_bytes(reader.take(8)?.try_into().unwrap());
+
+        let data = &value[reader.offset..];
+        if size != data.len() as u64 {
+            return Err(EnvelopeError::SizeMismatch {
+                expected: size,
+                actual: data.len(),
+            }
+            .into());
+        }
+
+        let filename = match filename {
+            "" => None,
+            name => Some(name.to_string()),
+        };
+        Ok(Envelope::new(
+            filename,
+            mime_type.to_string(),
+            data.to_vec(),
+        ))
+    }
+}
+
+impl Display for Envelope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.filename {
+            Some(filename) => write!(f, "'{}'", filename)?,
+            None => write!(f, "unnamed file")?,
+        }
+        write!(f, " ({}, {} bytes)", self.mime_type, self.size())
+    }
+}
+
+/// Returns whether `data` starts with the header of an envelope.
+pub fn is_envelope(data: &[u8]) -> bool {
+    data.len() > MAGIC.len() && data[..MAGIC.len()] == MAGIC
+}
+
+/// Guesses the MIME type of `path` from its extension.
+pub fn guess_mime_type<P: AsRef<Path>>(path: P) -> &'static str {
+    let extension = match path.as_ref().extension() {
+        Some(extension) => extension.to_string_lossy().to_ascii_lowercase(),
+        None => return DEFAULT_MIME_TYPE,
+    };
+
+    MIME_TYPES
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map_or(DEFAULT_MIME_TYPE, |(_, mime_type)| mime_type)
+}
+
+struct FieldReader<'a> {
+    value: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> FieldReader<'a> {
+    fn take(&mut self, needed: usize) -> Result<&'a [u8]> {
+        if self.value.len() - self.offset < needed {
+            return Err(EnvelopeError::Malformed.into());
+        }
+
+        let bytes = &self.value[self.offset..self.offset + needed];
+        self.offset += needed;
+        Ok(bytes)
+    }
+}
+
+#[derive(Debug)]
+pub enum EnvelopeError {
+    NotAnEnvelope,
+    UnsupportedVersion(u8),
+    FieldTooLong(&'static str, usize),
+    SizeMismatch { expected: u64, actual: usize },
+    Malformed,
+}
+
+impl std::error::Error for EnvelopeError {}
+
+impl Display for EnvelopeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnvelopeError::NotAnEnvelope => write!(f, "chunk data is not an embedded file"),
+            EnvelopeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported embedded file version {}", version)
+            }
+            EnvelopeError::FieldTooLong(field, length) => {
+                write!(f, "{} of {} bytes is too long to embed", field, length)
+            }
+            EnvelopeError::SizeMismatch { expected, actual } => write!(
+                f,
+                "embedded file should be {} bytes but {} were found",
+                expected, actual
+            ),
+            EnvelopeError::Malformed => write!(f, "embedded file header is malformed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn testing_envelope() -> Envelope {
+        let data = (0..=255).collect();
+        Envelope::new(
+            Some("blob.bin".to_string()),
+            DEFAULT_MIME_TYPE.to_string(),
+            data,
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let envelope = testing_envelope();
+        let bytes = envelope.as_bytes().unwrap();
+        assert!(is_envelope(&bytes));
+
+        let parsed = Envelope::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(parsed, envelope);
+        assert_eq!(parsed.filename(), Some("blob.bin"));
+        assert_eq!(parsed.size(), 256);
+    }
+
+    #[test]
+    fn test_unnamed_round_trip() {
+        let envelope = Envelope::new(None, "text/plain".to_string(), b"Hello".to_vec());
+        let bytes = envelope.as_bytes().unwrap();
+        let parsed = Envelope::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(parsed.filename(), None);
+        assert_eq!(parsed.to_string(), "unnamed file (text/plain, 5 bytes)");
+    }
+
+    #[test]
+    fn test_truncated_envelope() {
+        let bytes = testing_envelope().as_bytes().unwrap();
+
+        assert!(matches!(
+            Envelope::try_from(&bytes[..bytes.len() - 1]),
+            Err(Error::Envelope(EnvelopeError::SizeMismatch {
+                expected: 256,
+                actual: 255,
+            }))
+        ));
+        assert!(matches!(
+            Envelope::try_from(&bytes[..8]),
+            Err(Error::Envelope(EnvelopeError::Malformed))
+        ));
+    }
+
+    #[test]
+    fn test_not_an_envelope() {
+        assert!(!is_envelope(b"Hello"));
+        assert!(matches!(
+            Envelope::try_from(&b"Hello"[..]),
+            Err(Error::Envelope(EnvelopeError::NotAnEnvelope))
+        ));
+    }
+
+    #[test]
+    fn test_guess_mime_type() {
+        assert_eq!(guess_mime_type("report.PDF"), "application/pdf");
+        assert_eq!(guess_mime_type("notes.txt"), "text/plain");
+        assert_eq!(guess_mime_type("Makefile"), DEFAULT_MIME_TYPE);
+        assert_eq!(guess_mime_type("data.unknown"), DEFAULT_MIME_TYPE);
+    }
+}
@@ -0,0 +1,231 @@
+use crate::adam7::Adam7Error;
+use crate::capacity::CapacityError;
+use crate::chunk::ChunkParseError;
+use crate::chunk_type::ChunkTypeError;
+use crate::compression::CompressionError;
+use crate::crypto::CryptoError;
+use crate::envelope::EnvelopeError;
+use crate::filter::FilterError;
+use crate::fragment::FragmentError;
+use crate::idat::IdatError;
+use crate::ihdr::IhdrError;
+use crate::lsb::LsbError;
+use crate::png::PngError;
+use crate::sealed::SealedError;
+use crate::signature::SignatureError;
+use crate::text::TextError;
+use std::fmt::{Display, Formatter};
+
+/// Every error that can be produced by this crate.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Utf8(std::str::Utf8Error),
+    ChunkType(ChunkTypeError),
+    /// A chunk could not be parsed. `index` is the position of the chunk within the PNG and
+    /// `offset` is the byte offset at which the chunk starts, when parsed as part of a file.
+    Chunk {
+        index: Option<usize>,
+        offset: usize,
+        source: ChunkParseError,
+    },
+    Png(PngError),
+    Adam7(Adam7Error),
+    Capacity(CapacityError),
+    Crypto(CryptoError),
+    Compression(CompressionError),
+    Envelope(EnvelopeError),
+    Filter(FilterError),
+    Fragment(FragmentError),
+    Idat(IdatError),
+    Ihdr(IhdrError),
+    Lsb(LsbError),
+    Sealed(SealedError),
+    Signature(SignatureError),
+    Text(TextError),
+}
+
+impl Error {
+    /// Records where in a PNG file the chunk that caused this error was found.
+    pub(crate) fn at_chunk(self, index: usize, offset: usize) -> Error {
+        match self {
+            Error::Chunk { source, .. } => Error::Chunk {
+                index: Some(index),
+                offset,
+                source,
+            },
+            other => other,
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Utf8(err) => Some(err),
+            Error::ChunkType(err) => Some(err),
+            Error::Chunk { source, .. } => Some(source),
+            Error::Png(err) => Some(err),
+            Error::Adam7(err) => Some(err),
+            Error::Capacity(err) => Some(err),
+            Error::Crypto(err) => Some(err),
+            Error::Compression(err) => Some(err),
+            Error::Envelope(err) => Some(err),
+            Error::Filter(err) => Some(err),
+            Error::Fragment(err) => Some(err),
+            Error::Idat(err) => Some(err),
+            Error::Ihdr(err) => Some(err),
+            Error::Lsb(err) => Some(err),
+            Error::Sealed(err) => Some(err),
+            Error::Signature(err) => Some(err),
+            Error::Text(err) => Some(err),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Utf8(err) => write!(f, "chunk data is not valid UTF-8: {}", err),
+            Error::ChunkType(err) => write!(f, "{}", err),
+            Error::Chunk {
+                index: Some(index),
+                offset,
+                source,
+            } => write!(f, "chunk {} at byte {}: {}", index, offset, source),
+            Error::Chunk {
+                index: None,
+                source,
+                ..
+            } => write!(f, "{}", source),
+            Error::Png(err) => write!(f, "{}", err),
+            Error::Adam7(err) => write!(f, "{}", err),
+            Error::Capacity(err) => write!(f, "{}", err),
+            Error::Crypto(err) => write!(f, "{}", err),
+            Error::Compression(err) => write!(f, "{}", err),
+            Error::Envelope(err) => write!(f, "{}", err),
+            Error::Filter(err) => write!(f, "{}", err),
+            Error::Fragment(err) => write!(f, "{}", err),
+            Error::Idat(err) => write!(f, "{}", err),
+            Error::Ihdr(err) => write!(f, "{}", err),
+            Error::Lsb(err) => write!(f, "{}", err),
+            Error::Sealed(err) => write!(f, "{}", err),
+            Error::Signature(err) => write!(f, "{}", err),
+            Error::Text(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<std::str::Utf8Error> for Error {
+    fn from(err: std::str::Utf8Error) -> Self {
+        Error::Utf8(err)
+    }
+}
+
+impl From<ChunkTypeError> for Error {
+    fn from(err: ChunkTypeError) -> Self {
+        Error::ChunkType(err)
+    }
+}
+
+impl From<ChunkParseError> for Error {
+    fn from(err: ChunkParseError) -> Self {
+        Error::Chunk {
+            index: None,
+            offset: 0,
+            source: err,
+        }
+    }
+}
+
+impl From<PngError> for Error {
+    fn from(err: PngError) -> Self {
+        Error::Png(err)
+    }
+}
+
+impl From<Adam7Error> for Error {
+    fn from(err: Adam7Error) -> Self {
+        Error::Adam7(err)
+    }
+}
+
+impl From<CapacityError> for Error {
+    fn from(err: CapacityError) -> Self {
+        Error::Capacity(err)
+    }
+}
+
+impl From<CryptoError> for Error {
+    fn from(err: CryptoError) -> Self {
+        Error::Crypto(err)
+    }
+}
+
+impl From<CompressionError> for Error {
+    fn from(err: CompressionError) -> Self {
+        Error::Compression(err)
+    }
+}
+
+impl From<EnvelopeError> for Error {
+    fn from(err: EnvelopeError) -> Self {
+        Error::Envelope(err)
+    }
+}
+
+impl From<FilterError> for Error {
+    fn from(err: FilterError) -> Self {
+        Error::Filter(err)
+    }
+}
+
+impl From<FragmentError> for Error {
+    fn from(err: FragmentError) -> Self {
+        Error::Fragment(err)
+    }
+}
+
+impl From<IdatError> for Error {
+    fn from(err: IdatError) -> Self {
+        Error::Idat(err)
+    }
+}
+
+impl From<IhdrError> for Error {
+    fn from(err: IhdrError) -> Self {
+        Error::Ihdr(err)
+    }
+}
+
+impl From<LsbError> for Error {
+    fn from(err: LsbError) -> Self {
+        Error::Lsb(err)
+    }
+}
+
+impl From<SealedError> for Error {
+    fn from(err: SealedError) -> Self {
+        Error::Sealed(err)
+    }
+}
+
+impl From<SignatureError> for Error {
+    fn from(err: SignatureError) -> Self {
+        Error::Signature(err)
+    }
+}
+
+impl From<TextError> for Error {
+    fn from(err: TextError) -> Self {
+        Error::Text(err)
+    }
+}
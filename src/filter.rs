@@ -0,0 +1,421 @@
+use crate::adam7;
+use crate::idat::ImageData;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::Result;
+use std::fmt::{Display, Formatter};
+
+/// How a scanline was transformed to make it compress better. Each byte is stored as the
+/// difference to a prediction made from the bytes to its left and above it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterType {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+}
+
+impl FilterType {
+    pub const ALL: [FilterType; 5] = [
+        FilterType::None,
+        FilterType::Sub,
+        FilterType::Up,
+        FilterType::Average,
+        FilterType::Paeth,
+    ];
+
+    pub fn from_id(id: u8) -> Option<FilterType> {
+        FilterType::ALL.get(id as usize).copied()
+    }
+
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+
+    /// The value this filter predicts for a byte from the corresponding bytes of the pixel to
+    /// the left (`a`), above (`b`) and above and to the left (`c`).
+    fn predict(&self, a: u8, b: u8, c: u8) -> u8 {
+        match self {
+            FilterType::None => 0,
+            FilterType::Sub => a,
+            FilterType::Up => b,
+            FilterType::Average => ((a as u16 + b as u16) / 2) as u8,
+            FilterType::Paeth => paeth(a, b, c),
+        }
+    }
+}
+
+impl Display for FilterType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// The pixels of an image with the filters of its scanlines reversed, along with the filter
+/// type each scanline used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unfiltered {
+    /// Every row of the image back to back in the order they are stored, which for interlaced
+    /// images is pass by pass. Use `adam7::deinterlace` to get a full raster.
+    pub pixels: Vec<u8>,
+    pub filter_types: Vec<FilterType>,
+}
+
+/// Reverses the filter of every scanline of `image_data`.
+pub fn unfilter(image_data: &ImageData) -> Result<Unfiltered> {
+    let ihdr = image_data.ihdr();
+    let bytes_per_pixel = ihdr.bytes_per_pixel();
+
+    let mut unfiltered = Unfiltered {
+        pixels: Vec::with_capacity(image_data.data().len()),
+        filter_types: Vec::new(),
+    };
+    let mut scanlines = image_data.scanlines();
+    for (width, height) in adam7::sub_images(ihdr) {
+        let row_size = ihdr.row_size(width);
+        // The first row of every pass has nothing above it.
+        let first = unfiltered.pixels.len();
+        for line in scanlines.by_ref().take(height as usize) {
+            let filter_type = FilterType::from_id(line.filter_type)
+                .ok_or(FilterError::UnknownFilterType(line.filter_type))?;
+            let start = unfiltered.pixels.len();
+            unfiltered.pixels.extend_from_slice(line.data);
+            let (previous, row) = unfiltered.pixels.split_at_mut(start);
+            let previous = (start > first).then(|| &previous[start - row_size..]);
+            unfilter_row(filter_type, row, previous, bytes_per_pixel);
+            unfiltered.filter_types.push(filter_type);
+        }
+    }
+    Ok(unfiltered)
+}
+
+/// Filters every row of `pixels`, stored as in `Unfiltered`, with the filter type `choose`
+/// picks for it.
+pub fn filter(ihdr: &Ihdr, pixels: &[u8]) -> Result<ImageData> {
+    filter_rows(ihdr, pixels, |row, previous| choose(ihdr, row, previous))
+}
+
+/// Filters every row of `pixels`, stored as in `Unfiltered`, with the matching entry of
+/// `filter_types`.
+pub fn filter_with(ihdr: &Ihdr, pixels: &[u8], filter_types: &[FilterType]) -> Result<ImageData> {
+    let rows = adam7::sub_images(ihdr)
+        .iter()
+        .map(|&(_, height)| height as usize)
+        .sum();
+    if filter_types.len() != rows {
+        return Err(FilterError::BadFilterTypeCount {
+            expected: rows,
+            actual: filter_types.len(),
+        }
+        .into());
+    }
+    let mut filter_types = filter_types.iter();
+    filter_rows(ihdr, pixels, |_, _| *filter_types.next().unwrap())
+}
+
+/// Picks a filter type for `row` the way the spec recommends: none for images with a palette
+/// or fewer than 8 bits per sample, and otherwise the one that leaves the smallest sum of
+/// differences, taken as signed bytes.
+pub fn choose(ihdr: &Ihdr, row: &[u8], previous: Option<&[u8]>) -> FilterType {
+    if ihdr.color_type == ColorType::Indexed || ihdr.bit_depth < 8 {
+        return FilterType::None;
+    }
+
+    let bytes_per_pixel = ihdr.bytes_per_pixel();
+    let mut line = Vec::with_capacity(row.len());
+    FilterType::ALL
+        .into_iter()
+        .min_by_key(|&filter_type| {
+            line.clear();
+            line.extend(filter_row(filter_type, row, previous, bytes_per_pixel));
+            line.iter()
+                .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                .sum::<u64>()
+        })
+        .unwrap()
+}
+
+/// Reverses `filter_type` on `row` in place. `previous` is the already unfiltered row above,
+/// if there is one.
+pub fn unfilter_row(
+    filter_type: FilterType,
+    row: &mut [u8],
+    previous: Option<&[u8]>,
+    bytes_per_pixel: usize,
+) {
+    for i in 0..row.len() {
+        let (a, b, c) = neighbors(row, previous, i, bytes_per_pixel);
+        row[i] = row[i].wrapping_add(filter_type.predict(a, b, c));
+    }
+}
+
+/// Applies `filter_type` to `row`. `previous` is the unfiltered row above, if there is one.
+pub fn filter_row<'a>(
+    filter_type: FilterType,
+    row: &'a [u8],
+    previous: Option<&'a [u8]>,
+    bytes_per_pixel: usize,
+) -> impl Iterator<Item = u8> + 'a {
+    (0..row.len()).map(move |i| {
+        let (a, b, c) = neighbors(row, previous, i, bytes_per_pixel);
+        row[i].wrapping_sub(filter_type.predict(a, b, c))
+    })
+}
+
+fn filter_rows<F>(ihdr: &Ihdr, pixels: &[u8], mut filter_type: F) -> Result<ImageData>
+where
+    F: FnMut(&[u8], Option<&[u8]>) -> FilterType,
+{
+    let sub_images = adam7::sub_images(ihdr);
+    let expected = sub_images
+        .iter()
+        .map(|&(width, height)| ihdr.row_size(width) * height as usize)
+        .sum();
+    if pixels.len() != expected {
+        return Err(FilterError::BadPixelsSize {
+            expected,
+            actual: pixels.len(),
+        }
+        .into());
+    }
+
+    let bytes_per_pixel = ihdr.bytes_per_pixel();
+    let mut data = Vec::with_capacity(expected + ihdr.height as usize);
+    let mut rest = pixels;
+    for (width, height) in sub_images {
+        let row_size = ihdr.row_size(width);
+        let (sub_image, after) = rest.split_at(row_size * height as usize);
+        rest = after;
+
+        let mut previous = None;
+        for row in sub_image.chunks_exact(row_size) {
+            let filter_type = filter_type(row, previous);
+            data.push(filter_type.id());
+            data.extend(filter_row(filter_type, row, previous, bytes_per_pixel));
+            previous = Some(row);
+        }
+    }
+    ImageData::new(*ihdr, data)
+}
+
+/// The bytes at the same position of the pixel to the left of byte `i` of `row`, above it and
+/// above and to the left. Missing neighbors count as 0.
+fn neighbors(
+    row: &[u8],
+    previous: Option<&[u8]>,
+    i: usize,
+    bytes_per_pixel: usize,
+) -> (u8, u8, u8) {
+    let left = i.checked_sub(bytes_per_pixel);
+    let a = left.map_or(0, |left| row[left]);
+    let b = previous.map_or(0, |previous| previous[i]);
+    let c = previous
+        .zip(left)
+        .map_or(0, |(previous, left)| previous[left]);
+    (a, b, c)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[derive(Debug)]
+pub enum FilterError {
+    UnknownFilterType(u8),
+    BadPixelsSize { expected: usize, actual: usize },
+    BadFilterTypeCount { expected: usize, actual: usize },
+}
+
+impl std::error::Error for FilterError {}
+
+impl Display for FilterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterError::UnknownFilterType(id) => write!(f, "unknown filter type {}", id),
+            FilterError::BadPixelsSize { expected, actual } => {
+                write!(f, "pixels should be {} bytes but are {}", expected, actual)
+            }
+            FilterError::BadFilterTypeCount { expected, actual } => write!(
+                f,
+                "image has {} rows but {} filter types were given",
+                expected, actual
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::Interlace;
+    use crate::png;
+    use crate::Error;
+
+    fn sample() -> ImageData {
+        ImageData::from_png(&png::sample()).unwrap()
+    }
+
+    /// Pixels that do not repeat in any obvious way, so every filter changes them.
+    fn noise(ihdr: &Ihdr) -> Vec<u8> {
+        (0..ihdr.row_size(ihdr.width) * ihdr.height as usize)
+            .map(|i| (i * 7919 % 257) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_type_ids() {
+        for (id, filter_type) in FilterType::ALL.into_iter().enumerate() {
+            assert_eq!(FilterType::from_id(id as u8), Some(filter_type));
+            assert_eq!(filter_type.id(), id as u8);
+        }
+        assert_eq!(FilterType::from_id(5), None);
+    }
+
+    #[test]
+    fn test_paeth() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(10, 10, 20), 10);
+        assert_eq!(paeth(255, 0, 128), 128);
+    }
+
+    #[test]
+    fn test_known_rows() {
+        let previous = [10, 20, 30, 40];
+        let row = [15, 25, 35, 45];
+        let filtered = |filter_type| -> Vec<u8> {
+            filter_row(filter_type, &row, Some(&previous), 2).collect()
+        };
+
+        assert_eq!(filtered(FilterType::None), vec![15, 25, 35, 45]);
+        assert_eq!(filtered(FilterType::Sub), vec![15, 25, 20, 20]);
+        assert_eq!(filtered(FilterType::Up), vec![5, 5, 5, 5]);
+        assert_eq!(filtered(FilterType::Average), vec![10, 15, 13, 13]);
+        assert_eq!(filtered(FilterType::Paeth), vec![5, 5, 5, 5]);
+    }
+
+    #[test]
+    fn test_sample_round_trip() {
+        let image_data = sample();
+        let ihdr = *image_data.ihdr();
+        let unfiltered = unfilter(&image_data).unwrap();
+
+        // Filtering with the same filter types reproduces the file exactly.
+        let refiltered = filter_with(&ihdr, &unfiltered.pixels, &unfiltered.filter_types);
+        assert_eq!(refiltered.unwrap(), image_data);
+
+        for filter_type in FilterType::ALL {
+            let filter_types = vec![filter_type; ihdr.height as usize];
+            let filtered = filter_with(&ihdr, &unfiltered.pixels, &filter_types).unwrap();
+            assert!(filtered
+                .scanlines()
+                .all(|line| line.filter_type == filter_type.id()));
+            assert_eq!(unfilter(&filtered).unwrap().pixels, unfiltered.pixels);
+        }
+
+        let filtered = filter(&ihdr, &unfiltered.pixels).unwrap();
+        assert_eq!(unfilter(&filtered).unwrap().pixels, unfiltered.pixels);
+    }
+
+    #[test]
+    fn test_every_format_round_trip() {
+        for color_type in [
+            ColorType::Grayscale,
+            ColorType::Rgb,
+            ColorType::Indexed,
+            ColorType::GrayscaleAlpha,
+            ColorType::Rgba,
+        ] {
+            for &bit_depth in color_type.allowed_bit_depths() {
+                let ihdr = Ihdr {
+                    width: 13,
+                    height: 7,
+                    bit_depth,
+                    color_type,
+                    interlace: Interlace::None,
+                };
+                let pixels = noise(&ihdr);
+                let filter_types: Vec<FilterType> =
+                    (0..7).map(|row| FilterType::ALL[row % 5]).collect();
+
+                let filtered = filter_with(&ihdr, &pixels, &filter_types).unwrap();
+                let unfiltered = unfilter(&filtered).unwrap();
+                assert_eq!(unfiltered.pixels, pixels);
+                assert_eq!(unfiltered.filter_types, filter_types);
+
+                let filtered = filter(&ihdr, &pixels).unwrap();
+                assert_eq!(unfilter(&filtered).unwrap().pixels, pixels);
+            }
+        }
+    }
+
+    #[test]
+    fn test_interlaced_round_trip() {
+        for (width, height) in [(1, 1), (3, 2), (13, 7)] {
+            let ihdr = Ihdr {
+                width,
+                height,
+                bit_depth: 8,
+                color_type: ColorType::Rgb,
+                interlace: Interlace::Adam7,
+            };
+            let pixels = adam7::interlace(&ihdr, &noise(&ihdr)).unwrap();
+
+            let filtered = filter(&ihdr, &pixels).unwrap();
+            let unfiltered = unfilter(&filtered).unwrap();
+            assert_eq!(unfiltered.pixels, pixels);
+            assert_eq!(unfiltered.filter_types.len(), filtered.scanlines().count());
+            let refiltered = filter_with(&ihdr, &pixels, &unfiltered.filter_types).unwrap();
+            assert_eq!(refiltered, filtered);
+        }
+    }
+
+    #[test]
+    fn test_choose() {
+        let ihdr = *sample().ihdr();
+        let gradient: Vec<u8> = (0..2000).map(|i| (i / 4) as u8).collect();
+        assert_eq!(choose(&ihdr, &gradient, None), FilterType::Sub);
+        assert_eq!(choose(&ihdr, &gradient, Some(&gradient)), FilterType::Up);
+
+        let ihdr = Ihdr {
+            bit_depth: 8,
+            color_type: ColorType::Indexed,
+            ..ihdr
+        };
+        assert_eq!(choose(&ihdr, &gradient, Some(&gradient)), FilterType::None);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut data = sample().into_data();
+        data[0] = 5;
+        let ihdr = *sample().ihdr();
+        let image_data = ImageData::new(ihdr, data).unwrap();
+        assert!(matches!(
+            unfilter(&image_data),
+            Err(Error::Filter(FilterError::UnknownFilterType(5)))
+        ));
+
+        assert!(matches!(
+            filter(&ihdr, &[0; 10]),
+            Err(Error::Filter(FilterError::BadPixelsSize { actual: 10, .. }))
+        ));
+        assert!(matches!(
+            filter_with(&ihdr, &noise(&ihdr), &[FilterType::None]),
+            Err(Error::Filter(FilterError::BadFilterTypeCount {
+                expected: 500,
+                actual: 1
+            }))
+        ));
+    }
+}
@@ -0,0 +1,356 @@
+use crate::Result;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// Marks chunk data that holds one fragment of a larger payload.
+pub const MAGIC: [u8; 4] = *b"PMFR";
+
+/// Version 1 had no message id, so the fragments of two copies of a payload were mixed up, and
+/// is no longer accepted.
+const VERSION: u8 = 2;
+
+/// Size of the header at the start of every fragment: magic, version, message id, sequence
+/// number, fragment count and the checksum of the whole payload.
+pub const HEADER_SIZE: usize = MAGIC.len() + 1 + 4 + 4 + 4 + 4;
+
+/// One piece of a payload that was split across several chunks by `split`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fragment<'a> {
+    message_id: u32,
+    index: u32,
+    count: u32,
+    checksum: u32,
+    data: &'a [u8],
+}
+
+impl<'a> Fragment<'a> {
+    /// Random number picked by `split` for each payload it splits, shared by every fragment
+    /// of it.
+    pub fn message_id(&self) -> u32 {
+        self.message_id
+    }
+
+    /// The position of this fragment within the payload, starting at 0.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// How many fragments the payload was split into.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// CRC-32 of the whole payload, shared by every fragment of it.
+    pub fn checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Whether `other` was split from the same payload as this fragment.
+    pub fn is_sibling(&self, other: &Fragment) -> bool {
+        self.message_id == other.message_id
+            && self.count == other.count
+            && self.checksum == other.checksum
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Fragment<'a> {
+    type Error = crate::Error;
+
+    fn try_from(value: &'a [u8]) -> Result<Self> {
+        if !is_fragment(value) {
+            return Err(FragmentError::NotAFragment.into());
+        }
+        if value.len() < HEADER_SIZE {
+            return Err(FragmentError::Malformed.into());
+        }
+
+        let version = value[MAGIC.len()];
+        if version != VERSION {
+            return Err(FragmentError::UnsupportedVersion(version).into());
+        }
+
+        let field = |at: usize| u32::from_be_bytes(value[at..at + 4].try_into().unwrap());
+        let fragment = Fragment {
+            message_id: field(MAGIC.len() + 1),
+            index: field(MAGIC.len() + 5),
+            count: field(MAGIC.len() + 9),
+            checksum: field(MAGIC.len() + 13),
+            data: &value[HEADER_SIZE..],
+        };
+
+        if fragment.index >= fragment.count {
+            return Err(FragmentError::Malformed.into());
+        }
+
+        Ok(fragment)
+    }
+}
+
+/// Splits `payload` into pieces of at most `max_size` bytes each, including the fragment
+/// header. A payload that already fits is returned whole and without a header, so small
+/// messages are stored exactly as before.
+pub fn split(payload: &[u8], max_size: usize) -> Result<Vec<Vec<u8>>> {
+    if payload.len() <= max_size {
+        return Ok(vec![payload.to_vec()]);
+    }
+    if max_size <= HEADER_SIZE {
+        return Err(FragmentError::MaxSizeTooSmall(max_size).into());
+    }
+
+    let pieces = payload.chunks(max_size - HEADER_SIZE);
+    let count = u32::try_from(pieces.len()).map_err(|_| FragmentError::TooManyFragments)?;
+    let checksum = crc::crc32::checksum_ieee(payload);
+    let message_id = OsRng.next_u32();
+
+    let fragments = pieces
+        .enumerate()
+        .map(|(index, piece)| {
+            let mut fragment = MAGIC.to_vec();
+            fragment.push(VERSION);
+            fragment.extend_from_slice(&message_id.to_be_bytes());
+            fragment.extend_from_slice(&(index as u32).to_be_bytes());
+            fragment.extend_from_slice(&count.to_be_bytes());
+            fragment.extend_from_slice(&checksum.to_be_bytes());
+            fragment.extend_from_slice(piece);
+            fragment
+        })
+        .collect();
+
+    Ok(fragments)
+}
+
+/// Reassembles the payload that begins with `parts[0]`, the data of the first chunk holding
+/// it. If that is a fragment, the rest of its fragments are taken from `parts` in sequence
+/// order, whatever order the chunks were in. Fragments of other payloads are ignored.
+pub fn join(parts: &[&[u8]]) -> Result<Vec<u8>> {
+    let first = match parts.first() {
+        Some(first) if is_fragment(first) => Fragment::try_from(*first)?,
+        Some(first) => return Ok(first.to_vec()),
+        None => return Err(FragmentError::Missing(0).into()),
+    };
+
+    // Keyed by sequence number rather than preallocated, since the count comes from the file.
+    let mut fragments = BTreeMap::new();
+    for part in parts.iter().filter(|part| is_fragment(part)) {
+        let fragment = Fragment::try_from(*part)?;
+        if !fragment.is_sibling(&first) {
+            continue;
+        }
+
+        if fragments.insert(fragment.index, fragment.data).is_some() {
+            return Err(FragmentError::Duplicate(fragment.index).into());
+        }
+    }
+
+    let mut payload = Vec::new();
+    for index in 0..first.count {
+        match fragments.get(&index) {
+            Some(data) => payload.extend_from_slice(data),
+            None => return Err(FragmentError::Missing(index).into()),
+        }
+    }
+
+    let actual = crc::crc32::checksum_ieee(&payload);
+    if actual != first.checksum {
+        return Err(FragmentError::BadChecksum {
+            expected: first.checksum,
+            actual,
+        }
+        .into());
+    }
+
+    Ok(payload)
+}
+
+/// Returns whether `data` starts with a fragment header.
+pub fn is_fragment(data: &[u8]) -> bool {
+    data.len() > MAGIC.len() && data[..MAGIC.len()] == MAGIC
+}
+
+/// Whether the chunk data `other` holds another fragment of the payload that `first` holds a
+/// fragment of.
+pub fn same_payload(first: &[u8], other: &[u8]) -> bool {
+    match (Fragment::try_from(first), Fragment::try_from(other)) {
+        (Ok(first), Ok(other)) => first.is_sibling(&other),
+        _ => false,
+    }
+}
+
+#[derive(Debug)]
+pub enum FragmentError {
+    NotAFragment,
+    Fragmented,
+    UnsupportedVersion(u8),
+    Malformed,
+    MaxSizeTooSmall(usize),
+    TooManyFragments,
+    Missing(u32),
+    Duplicate(u32),
+    BadChecksum { expected: u32, actual: u32 },
+}
+
+impl std::error::Error for FragmentError {}
+
+impl Display for FragmentError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FragmentError::NotAFragment => write!(f, "chunk data is not a message fragment"),
+            FragmentError::Fragmented => write!(
+                f,
+                "message is split across several chunks and has to be reassembled"
+            ),
+            FragmentError::UnsupportedVersion(version) => {
+                write!(f, "unsupported fragment version {}", version)
+            }
+            FragmentError::Malformed => write!(f, "fragment header is malformed"),
+            FragmentError::MaxSizeTooSmall(size) => write!(
+                f,
+                "maximum chunk size of {} bytes leaves no room for data after the {} byte \
+                 fragment header",
+                size, HEADER_SIZE
+            ),
+            FragmentError::TooManyFragments => write!(f, "message needs too many fragments"),
+            FragmentError::Missing(index) => {
+                write!(f, "fragment {} of the message is missing", index)
+            }
+            FragmentError::Duplicate(index) => write!(
+                f,
+                "fragment {} of the message appears more than once",
+                index
+            ),
+            FragmentError::BadChecksum { expected, actual } => write!(
+                f,
+                "reassembled message has checksum {} but {} was expected",
+                actual, expected
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn testing_payload() -> Vec<u8> {
+        (0..=255).cycle().take(1000).collect()
+    }
+
+    fn as_slices(fragments: &[Vec<u8>]) -> Vec<&[u8]> {
+        fragments.iter().map(Vec::as_slice).collect()
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        let payload = testing_payload();
+        let fragments = split(&payload, 100).unwrap();
+
+        assert_eq!(fragments.len(), 13);
+        assert!(fragments.iter().all(|fragment| fragment.len() <= 100));
+        assert_eq!(join(&as_slices(&fragments)).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_small_payload_is_not_split() {
+        let fragments = split(b"Hello", 100).unwrap();
+        assert_eq!(fragments, vec![b"Hello".to_vec()]);
+        assert_eq!(join(&as_slices(&fragments)).unwrap(), b"Hello");
+    }
+
+    #[test]
+    fn test_join_out_of_order() {
+        let payload = testing_payload();
+        let fragments = split(&payload, 300).unwrap();
+
+        let mut parts = as_slices(&fragments);
+        parts[1..].reverse();
+        assert_eq!(join(&parts).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_join_ignores_other_payloads() {
+        let payload = testing_payload();
+        let fragments = split(&payload, 300).unwrap();
+        let others = split(&[7; 500], 300).unwrap();
+
+        let mut parts = as_slices(&fragments);
+        parts.insert(2, &others[0]);
+        parts.push(&others[1]);
+        assert_eq!(join(&parts).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_join_ignores_copies_of_the_payload() {
+        let payload = testing_payload();
+        let fragments = split(&payload, 300).unwrap();
+        let copy = split(&payload, 300).unwrap();
+        assert_ne!(fragments, copy);
+
+        let mut parts = as_slices(&fragments);
+        parts.extend(as_slices(&copy));
+        assert_eq!(join(&parts).unwrap(), payload);
+        assert!(!same_payload(&fragments[0], &copy[1]));
+        assert!(same_payload(&fragments[0], &fragments[1]));
+    }
+
+    #[test]
+    fn test_missing_fragment() {
+        let fragments = split(&testing_payload(), 300).unwrap();
+        let mut parts = as_slices(&fragments);
+        parts.remove(2);
+
+        assert!(matches!(
+            join(&parts),
+            Err(Error::Fragment(FragmentError::Missing(2)))
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_fragment() {
+        let fragments = split(&testing_payload(), 300).unwrap();
+        let mut parts = as_slices(&fragments);
+        parts.push(&fragments[1]);
+
+        assert!(matches!(
+            join(&parts),
+            Err(Error::Fragment(FragmentError::Duplicate(1)))
+        ));
+    }
+
+    #[test]
+    fn test_bad_checksum() {
+        let mut fragments = split(&testing_payload(), 300).unwrap();
+        let last = fragments[1].len() - 1;
+        fragments[1][last] ^= 0xFF;
+
+        assert!(matches!(
+            join(&as_slices(&fragments)),
+            Err(Error::Fragment(FragmentError::BadChecksum { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_max_size_too_small() {
+        assert!(matches!(
+            split(&testing_payload(), HEADER_SIZE),
+            Err(Error::Fragment(FragmentError::MaxSizeTooSmall(_)))
+        ));
+    }
+
+    #[test]
+    fn test_fragment_header() {
+        let fragments = split(&testing_payload(), 300).unwrap();
+        let fragment = Fragment::try_from(fragments[3].as_slice()).unwrap();
+
+        assert_eq!(fragment.index(), 3);
+        assert_eq!(fragment.count(), 4);
+        assert_eq!(fragment.data().len(), 1000 - 3 * (300 - HEADER_SIZE));
+        assert!(Fragment::try_from(&fragments[3][..HEADER_SIZE - 1]).is_err());
+    }
+}
@@ -0,0 +1,323 @@
+use crate::adam7;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::Ihdr;
+use crate::png::Png;
+use crate::Result;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+pub const IDAT: &str = "IDAT";
+
+/// Deflate cannot expand data by more than about this factor, which bounds how much of the
+/// size `IHDR` claims is worth reserving up front.
+const MAX_INFLATE_RATIO: usize = 1032;
+
+/// The decompressed image data of a PNG: every scanline, prefixed with the type of the filter
+/// that was applied to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageData {
+    ihdr: Ihdr,
+    data: Vec<u8>,
+}
+
+impl ImageData {
+    /// Wraps decompressed image data, checking that its size matches `ihdr`.
+    pub fn new(ihdr: Ihdr, data: Vec<u8>) -> Result<ImageData> {
+        let expected = data_size(&ihdr)?;
+        if data.len() != expected {
+            return Err(IdatError::BadSize {
+                expected,
+                actual: data.len(),
+            }
+            .into());
+        }
+        Ok(ImageData { ihdr, data })
+    }
+
+    /// Concatenates the `IDAT` chunks of `png` and inflates them. Anything the zlib stream
+    /// holds beyond what `IHDR` calls for is ignored, and no more is allocated up front than the
+    /// stream could inflate to, however large `IHDR` claims the image is.
+    pub fn from_png(png: &Png) -> Result<ImageData> {
+        let ihdr = png.ihdr()?;
+        let expected = data_size(&ihdr)?;
+
+        let compressed = compressed(png);
+        if compressed.is_empty() {
+            return Err(IdatError::Missing.into());
+        }
+        let mut data =
+            Vec::with_capacity(expected.min(compressed.len().saturating_mul(MAX_INFLATE_RATIO)));
+        ZlibDecoder::new(compressed.as_slice())
+            .take(expected as u64)
+            .read_to_end(&mut data)
+            .map_err(|_| IdatError::Corrupt)?;
+
+        ImageData::new(ihdr, data)
+    }
+
+    pub fn ihdr(&self) -> &Ihdr {
+        &self.ihdr
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// The scanlines of the image in the order they are stored, still filtered. For interlaced
+    /// images these are the rows of each pass in turn, see `adam7::sub_images`.
+    pub fn scanlines(&self) -> impl Iterator<Item = Scanline<'_>> {
+        let ihdr = self.ihdr;
+        let mut rest = self.data.as_slice();
+        adam7::sub_images(&ihdr)
+            .into_iter()
+            .flat_map(move |(width, height)| {
+                let size = (ihdr.row_size(width) + 1) * height as usize;
+                let (lines, after) = rest.split_at(size);
+                rest = after;
+                lines.chunks_exact(ihdr.row_size(width) + 1)
+            })
+            .map(|line| Scanline {
+                filter_type: line[0],
+                data: &line[1..],
+            })
+    }
+
+    /// Compresses the image data into a zlib stream.
+    pub fn deflate(&self) -> Result<Vec<u8>> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&self.data)?;
+        Ok(encoder.finish()?)
+    }
+
+    /// Compresses the image data and splits it into `IDAT` chunks of at most
+    /// `max_chunk_size` bytes each.
+    pub fn to_chunks(&self, max_chunk_size: usize) -> Result<Vec<Chunk>> {
+        split(&self.deflate()?, max_chunk_size)
+    }
+}
+
+/// A row of the image as stored, before its filter is reversed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scanline<'a> {
+    pub filter_type: u8,
+    pub data: &'a [u8],
+}
+
+/// Size of the decompressed image data of an image described by `ihdr`.
+pub fn data_size(ihdr: &Ihdr) -> Result<usize> {
+    adam7::sub_images(ihdr)
+        .into_iter()
+        .try_fold(0usize, |size, (width, height)| {
+            (ihdr.row_size(width) + 1)
+                .checked_mul(height as usize)?
+                .checked_add(size)
+        })
+        .ok_or_else(|| IdatError::TooLarge.into())
+}
+
+/// The zlib stream of `png`, which is the data of all of its `IDAT` chunks in order.
+pub fn compressed(png: &Png) -> Vec<u8> {
+    png.chunks_by_type(IDAT)
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect()
+}
+
+/// Splits a zlib stream into `IDAT` chunks of at most `max_chunk_size` bytes each.
+pub fn split(compressed: &[u8], max_chunk_size: usize) -> Result<Vec<Chunk>> {
+    if max_chunk_size == 0 || max_chunk_size > Chunk::MAX_DATA_LENGTH {
+        return Err(IdatError::BadChunkSize(max_chunk_size).into());
+    }
+    let chunk_type = ChunkType::from_str(IDAT)?;
+    Ok(compressed
+        .chunks(max_chunk_size)
+        .map(|data| Chunk::new(chunk_type, data.to_vec()))
+        .collect())
+}
+
+/// Replaces the `IDAT` chunks of `png` with `chunks`, placed where the first one was.
+pub fn replace(png: &mut Png, chunks: Vec<Chunk>) -> Result<()> {
+    let first = png.position_of(IDAT).ok_or(IdatError::Missing)?;
+    while png.remove_chunk(IDAT).is_ok() {}
+    for (offset, chunk) in chunks.into_iter().enumerate() {
+        png.insert_chunk(first + offset, chunk)?;
+    }
+    Ok(())
+}
+
+/// Splits the image data of `png` into `IDAT` chunks of at most `max_chunk_size` bytes each,
+/// without recompressing it.
+pub fn rechunk(png: &mut Png, max_chunk_size: usize) -> Result<()> {
+    let chunks = split(&compressed(png), max_chunk_size)?;
+    replace(png, chunks)
+}
+
+/// Size of the largest `IDAT` chunk of `png`, which is a good size to split new image data
+/// into to keep the layout of the file.
+pub fn max_chunk_size(png: &Png) -> Option<usize> {
+    png.chunks_by_type(IDAT).map(Chunk::length).max()
+}
+
+#[derive(Debug)]
+pub enum IdatError {
+    Missing,
+    TooLarge,
+    BadSize { expected: usize, actual: usize },
+    BadChunkSize(usize),
+    Corrupt,
+}
+
+impl std::error::Error for IdatError {}
+
+impl Display for IdatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IdatError::Missing => write!(f, "image has no IDAT chunk"),
+            IdatError::TooLarge => write!(f, "image is too large to decompress"),
+            IdatError::BadSize { expected, actual } => write!(
+                f,
+                "image data should be {} bytes but is {}",
+                expected, actual
+            ),
+            IdatError::BadChunkSize(size) => write!(
+                f,
+                "cannot split image data into chunks of {} bytes, they must hold between 1 \
+                 and {}",
+                size,
+                Chunk::MAX_DATA_LENGTH
+            ),
+            IdatError::Corrupt => write!(f, "image data is corrupt"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::{ColorType, Interlace};
+    use crate::png::sample;
+    use crate::Error;
+
+    #[test]
+    fn test_scanlines() {
+        let png = sample();
+        let image_data = ImageData::from_png(&png).unwrap();
+        let ihdr = png.ihdr().unwrap();
+
+        assert_eq!(image_data.data().len(), (500 * 4 + 1) * 500);
+        let scanlines: Vec<Scanline> = image_data.scanlines().collect();
+        assert_eq!(scanlines.len(), ihdr.height as usize);
+        assert!(scanlines
+            .iter()
+            .all(|line| line.data.len() == 2000 && line.filter_type <= 4));
+    }
+
+    #[test]
+    fn test_deflate_round_trip() {
+        let image_data = ImageData::from_png(&sample()).unwrap();
+        let mut png = sample();
+
+        let chunks = image_data.to_chunks(1000).unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| chunk.length() <= 1000));
+        replace(&mut png, chunks).unwrap();
+
+        let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert_eq!(png.chunks()[0].chunk_type().to_string(), "IHDR");
+        assert_eq!(png.validate(), vec![]);
+        assert_eq!(ImageData::from_png(&png).unwrap(), image_data);
+    }
+
+    #[test]
+    fn test_rechunk() {
+        let original = sample();
+        let mut png = sample();
+
+        rechunk(&mut png, 100).unwrap();
+        assert_eq!(max_chunk_size(&png), Some(100));
+        assert_eq!(compressed(&png), compressed(&original));
+        assert_eq!(png.validate(), vec![]);
+
+        rechunk(&mut png, Chunk::MAX_DATA_LENGTH).unwrap();
+        assert_eq!(png.chunks_by_type(IDAT).count(), 1);
+        assert!(matches!(
+            rechunk(&mut png, 0),
+            Err(Error::Idat(IdatError::BadChunkSize(0)))
+        ));
+    }
+
+    #[test]
+    fn test_bad_image_data() {
+        let mut png = sample();
+        let ihdr = png.ihdr().unwrap();
+        assert!(matches!(
+            ImageData::new(ihdr, vec![0; 10]),
+            Err(Error::Idat(IdatError::BadSize {
+                expected: 1_000_500,
+                actual: 10
+            }))
+        ));
+
+        let mut data = compressed(&png);
+        data.truncate(data.len() / 2);
+        replace(&mut png, split(&data, 1000).unwrap()).unwrap();
+        assert!(ImageData::from_png(&png).is_err());
+
+        while png.remove_chunk(IDAT).is_ok() {}
+        assert!(matches!(
+            ImageData::from_png(&png),
+            Err(Error::Idat(IdatError::Missing))
+        ));
+    }
+
+    #[test]
+    fn test_claimed_size_too_large() {
+        let mut png = sample();
+        let ihdr = Ihdr {
+            width: 60_000,
+            height: 60_000,
+            ..png.ihdr().unwrap()
+        };
+        png.remove_chunk("IHDR").unwrap();
+        png.insert_chunk(
+            0,
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr.as_bytes()),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            ImageData::from_png(&png),
+            Err(Error::Idat(IdatError::BadSize {
+                expected: 14_400_060_000,
+                actual: 1_000_500
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_data_size() {
+        let ihdr = Ihdr {
+            width: 9,
+            height: 3,
+            bit_depth: 1,
+            color_type: ColorType::Grayscale,
+            interlace: Interlace::None,
+        };
+        assert_eq!(data_size(&ihdr).unwrap(), 9);
+
+        // The third pass is empty, the others have rows of a byte each plus their filter type,
+        // except for the last, whose rows of 9 pixels take up 2 bytes.
+        let ihdr = Ihdr {
+            interlace: Interlace::Adam7,
+            ..ihdr
+        };
+        assert_eq!(data_size(&ihdr).unwrap(), 2 + 2 + 2 + 2 + 2 * 2 + 3);
+    }
+}
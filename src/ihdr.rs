@@ -0,0 +1,346 @@
+use crate::Result;
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+
+/// Size of the data of a well-formed `IHDR` chunk.
+pub const DATA_SIZE: usize = 13;
+
+/// Largest width or height the spec allows, 2^31 - 1.
+pub const MAX_DIMENSION: u32 = i32::MAX as u32;
+
+/// How the samples of each pixel are to be interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    pub fn from_id(id: u8) -> Option<ColorType> {
+        match id {
+            0 => Some(ColorType::Grayscale),
+            2 => Some(ColorType::Rgb),
+            3 => Some(ColorType::Indexed),
+            4 => Some(ColorType::GrayscaleAlpha),
+            6 => Some(ColorType::Rgba),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Rgb => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::Rgba => 6,
+        }
+    }
+
+    /// Number of samples that make up one pixel.
+    pub fn channels(&self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// The bit depths the spec allows for this color type.
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorType::Grayscale => write!(f, "grayscale"),
+            ColorType::Rgb => write!(f, "RGB"),
+            ColorType::Indexed => write!(f, "indexed"),
+            ColorType::GrayscaleAlpha => write!(f, "grayscale with alpha"),
+            ColorType::Rgba => write!(f, "RGBA"),
+        }
+    }
+}
+
+/// The order in which the pixels of the image are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interlace {
+    None,
+    Adam7,
+}
+
+impl Display for Interlace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Interlace::None => write!(f, "not interlaced"),
+            Interlace::Adam7 => write!(f, "Adam7 interlaced"),
+        }
+    }
+}
+
+/// The contents of an `IHDR` chunk, checked against the combinations the spec allows.
+///
+/// The compression and filter methods have a single legal value each, so they are validated
+/// but not stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub interlace: Interlace,
+}
+
+impl Ihdr {
+    /// Number of bits one pixel takes up.
+    pub fn bits_per_pixel(&self) -> usize {
+        self.bit_depth as usize * self.color_type.channels()
+    }
+
+    /// Number of bytes one pixel takes up, rounded up to a whole byte. This is the distance
+    /// filters look back to find the corresponding byte of the previous pixel.
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8)
+    }
+
+    /// Number of bytes a row of `width` pixels takes up, not counting its filter type byte.
+    pub fn row_size(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(DATA_SIZE);
+        bytes.extend_from_slice(&self.width.to_be_bytes());
+        bytes.extend_from_slice(&self.height.to_be_bytes());
+        bytes.push(self.bit_depth);
+        bytes.push(self.color_type.id());
+        bytes.push(0);
+        bytes.push(0);
+        bytes.push(match self.interlace {
+            Interlace::None => 0,
+            Interlace::Adam7 => 1,
+        });
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    type Error = crate::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self> {
+        if value.len() != DATA_SIZE {
+            return Err(IhdrError::BadLength(value.len()).into());
+        }
+
+        let width = u32::from_be_bytes(value[0..4].try_into().unwrap());
+        let height = u32::from_be_bytes(value[4..8].try_into().unwrap());
+        if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(IhdrError::BadDimensions { width, height }.into());
+        }
+
+        let bit_depth = value[8];
+        let color_type = ColorType::from_id(value[9]).ok_or(IhdrError::BadColorType(value[9]))?;
+        if !color_type.allowed_bit_depths().contains(&bit_depth) {
+            return Err(IhdrError::BadBitDepth {
+                bit_depth,
+                color_type,
+            }
+            .into());
+        }
+
+        if value[10] != 0 {
+            return Err(IhdrError::BadCompressionMethod(value[10]).into());
+        }
+        if value[11] != 0 {
+            return Err(IhdrError::BadFilterMethod(value[11]).into());
+        }
+        let interlace = match value[12] {
+            0 => Interlace::None,
+            1 => Interlace::Adam7,
+            other => return Err(IhdrError::BadInterlaceMethod(other).into()),
+        };
+
+        Ok(Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlace,
+        })
+    }
+}
+
+impl Display for Ihdr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{}, {}-bit {}, {}",
+            self.width, self.height, self.bit_depth, self.color_type, self.interlace
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IhdrError {
+    Missing,
+    NotFirst,
+    BadLength(usize),
+    BadDimensions {
+        width: u32,
+        height: u32,
+    },
+    BadColorType(u8),
+    BadBitDepth {
+        bit_depth: u8,
+        color_type: ColorType,
+    },
+    BadCompressionMethod(u8),
+    BadFilterMethod(u8),
+    BadInterlaceMethod(u8),
+}
+
+impl std::error::Error for IhdrError {}
+
+impl Display for IhdrError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IhdrError::Missing => write!(f, "image has no IHDR chunk"),
+            IhdrError::NotFirst => write!(f, "IHDR is not the first chunk of the image"),
+            IhdrError::BadLength(length) => {
+                write!(f, "IHDR should be {} bytes but is {}", DATA_SIZE, length)
+            }
+            IhdrError::BadDimensions { width, height } => write!(
+                f,
+                "image size of {}x{} is invalid, both must be between 1 and {}",
+                width, height, MAX_DIMENSION
+            ),
+            IhdrError::BadColorType(id) => write!(f, "unknown color type {}", id),
+            IhdrError::BadBitDepth {
+                bit_depth,
+                color_type,
+            } => write!(
+                f,
+                "bit depth {} is not allowed for {} images",
+                bit_depth, color_type
+            ),
+            IhdrError::BadCompressionMethod(method) => {
+                write!(f, "unknown compression method {}", method)
+            }
+            IhdrError::BadFilterMethod(method) => write!(f, "unknown filter method {}", method),
+            IhdrError::BadInterlaceMethod(method) => {
+                write!(f, "unknown interlace method {}", method)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn testing_ihdr() -> Ihdr {
+        Ihdr {
+            width: 640,
+            height: 480,
+            bit_depth: 8,
+            color_type: ColorType::Rgba,
+            interlace: Interlace::None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ihdr = testing_ihdr();
+        let bytes = ihdr.as_bytes();
+        assert_eq!(bytes.len(), DATA_SIZE);
+        assert_eq!(Ihdr::try_from(bytes.as_slice()).unwrap(), ihdr);
+        assert_eq!(ihdr.to_string(), "640x480, 8-bit RGBA, not interlaced");
+    }
+
+    #[test]
+    fn test_pixel_sizes() {
+        let ihdr = testing_ihdr();
+        assert_eq!(ihdr.bits_per_pixel(), 32);
+        assert_eq!(ihdr.bytes_per_pixel(), 4);
+        assert_eq!(ihdr.row_size(640), 2560);
+
+        let ihdr = Ihdr {
+            bit_depth: 1,
+            color_type: ColorType::Grayscale,
+            ..testing_ihdr()
+        };
+        assert_eq!(ihdr.bytes_per_pixel(), 1);
+        assert_eq!(ihdr.row_size(9), 2);
+    }
+
+    #[test]
+    fn test_bad_bit_depth() {
+        let mut bytes = testing_ihdr().as_bytes();
+        bytes[8] = 4;
+        assert!(matches!(
+            Ihdr::try_from(bytes.as_slice()),
+            Err(Error::Ihdr(IhdrError::BadBitDepth {
+                bit_depth: 4,
+                color_type: ColorType::Rgba,
+            }))
+        ));
+
+        bytes[9] = 0;
+        assert!(Ihdr::try_from(bytes.as_slice()).is_ok());
+    }
+
+    #[test]
+    fn test_bad_fields() {
+        let bytes = testing_ihdr().as_bytes();
+        let with = |index: usize, value: u8| {
+            let mut bytes = bytes.clone();
+            bytes[index] = value;
+            Ihdr::try_from(bytes.as_slice())
+        };
+
+        assert!(matches!(
+            with(9, 5),
+            Err(Error::Ihdr(IhdrError::BadColorType(5)))
+        ));
+        assert!(matches!(
+            with(10, 1),
+            Err(Error::Ihdr(IhdrError::BadCompressionMethod(1)))
+        ));
+        assert!(matches!(
+            with(11, 1),
+            Err(Error::Ihdr(IhdrError::BadFilterMethod(1)))
+        ));
+        assert!(matches!(
+            with(12, 2),
+            Err(Error::Ihdr(IhdrError::BadInterlaceMethod(2)))
+        ));
+        assert!(matches!(
+            with(0, 0x80),
+            Err(Error::Ihdr(IhdrError::BadDimensions { .. }))
+        ));
+        assert!(matches!(
+            Ihdr::try_from(&[0; 13][..]),
+            Err(Error::Ihdr(IhdrError::BadDimensions {
+                width: 0,
+                height: 0
+            }))
+        ));
+        assert!(matches!(
+            Ihdr::try_from(&bytes[..12]),
+            Err(Error::Ihdr(IhdrError::BadLength(12)))
+        ));
+    }
+}
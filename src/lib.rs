@@ -0,0 +1,39 @@
+//! Hide messages inside PNG files by storing them in custom chunks.
+//!
+//! The [`Png`], [`Chunk`] and [`ChunkType`] types model a PNG file, and the functions in
+//! [`ops`] implement the encode, decode and remove operations used by the `png-msg` binary.
+
+pub mod adam7;
+pub mod capacity;
+pub mod chunk;
+pub mod chunk_ref;
+pub mod chunk_type;
+pub mod compression;
+pub mod crypto;
+pub mod envelope;
+pub mod error;
+pub mod filter;
+pub mod fragment;
+pub mod idat;
+pub mod ihdr;
+pub mod lsb;
+pub mod ops;
+pub mod png;
+pub mod png_ref;
+pub mod report;
+pub mod sealed;
+pub mod signature;
+pub mod stream;
+pub mod text;
+pub mod trailer;
+pub mod validate;
+
+pub use chunk::Chunk;
+pub use chunk_ref::ChunkRef;
+pub use chunk_type::ChunkType;
+pub use error::Error;
+pub use png::{ChunkPosition, Diagnostic, Png};
+pub use png_ref::PngRef;
+pub use stream::{ChunkReader, ChunkWriter};
+
+pub type Result<T> = std::result::Result<T, Error>;
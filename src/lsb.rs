@@ -0,0 +1,439 @@
+use crate::adam7;
+use crate::capacity::{CapacityError, Embedding};
+use crate::chunk_type::ChunkType;
+use crate::filter::{self, Unfiltered};
+use crate::idat::{self, IdatError, ImageData};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::Result;
+use std::fmt::{Display, Formatter};
+
+/// Marks image data that holds a message written by `embed`.
+pub const MAGIC: [u8; 4] = *b"PMLS";
+
+/// Size of the header hidden in front of the payload: magic, the chunk type the message is
+/// stored under, the number of bits per sample the payload uses and the length of the payload.
+pub const HEADER_SIZE: usize = MAGIC.len() + 4 + 1 + 4;
+
+/// The numbers of low bits of each sample that can be given to `embed`.
+pub const BITS_PER_SAMPLE: [u8; 3] = [1, 2, 4];
+
+/// Hides `payload` in the `bits` least significant bits of every color sample of the image,
+/// leaving alpha alone. `chunk_type` is recorded so that the message can be looked up by type
+/// like one stored in a chunk. The header always uses a single bit per sample, so `extract`
+/// can find out what `bits` was.
+///
+/// Interlaced images carry the message in raster order like any other. Every scanline is
+/// filtered again with the filter type it had before, and the image data is split into `IDAT`
+/// chunks no larger than the largest one it had before.
+pub fn embed(png: &mut Png, chunk_type: &ChunkType, payload: &[u8], bits: u8) -> Result<()> {
+    let ihdr = carrier_ihdr(png)?;
+    let capacity = capacity(&ihdr, bits)?;
+    let mut image = decode(png)?;
+    if payload.len() > capacity || carrier_count(&ihdr) < HEADER_SIZE * 8 {
+        return Err(CapacityError::too_large(Embedding::Lsb { bits }, payload, capacity).into());
+    }
+
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&chunk_type.bytes());
+    header.push(bits);
+    header.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    let mut carriers = carrier_bytes(&ihdr);
+    write_bits(
+        &mut image.pixels,
+        carriers.by_ref().take(HEADER_SIZE * 8),
+        &header,
+        1,
+    );
+    write_bits(&mut image.pixels, carriers, payload, bits);
+
+    let max_chunk_size = idat::max_chunk_size(png).ok_or(IdatError::Missing)?;
+    let pixels = adam7::interlace(&ihdr, &image.pixels)?;
+    let image_data = filter::filter_with(&ihdr, &pixels, &image.filter_types)?;
+    let chunks = image_data.to_chunks(max_chunk_size)?;
+    idat::replace(png, chunks)
+}
+
+/// Returns the payload hidden in the pixels by `embed` under `chunk_type`, or `None` if the
+/// image does not hold a message stored under that type.
+pub fn extract(png: &Png, chunk_type: &str) -> Result<Option<Vec<u8>>> {
+    let ihdr = carrier_ihdr(png)?;
+    let image = decode(png)?;
+    let payload_carriers = match carrier_count(&ihdr).checked_sub(HEADER_SIZE * 8) {
+        Some(payload_carriers) => payload_carriers,
+        None => return Ok(None),
+    };
+
+    let mut carriers = carrier_bytes(&ihdr);
+    let header = read_bits(&image.pixels, carriers.by_ref(), HEADER_SIZE, 1);
+    if header[..MAGIC.len()] != MAGIC
+        || &header[MAGIC.len()..MAGIC.len() + 4] != chunk_type.as_bytes()
+    {
+        return Ok(None);
+    }
+
+    let bits = header[MAGIC.len() + 4];
+    let length = u32::from_be_bytes(header[HEADER_SIZE - 4..].try_into().unwrap()) as usize;
+    if !BITS_PER_SAMPLE.contains(&bits) || length > payload_carriers * bits as usize / 8 {
+        return Err(LsbError::Corrupt.into());
+    }
+    Ok(Some(read_bits(&image.pixels, carriers, length, bits)))
+}
+
+/// Number of payload bytes `embed` can hide in an image described by `ihdr`, using `bits`
+/// bits of every color sample.
+pub fn capacity(ihdr: &Ihdr, bits: u8) -> Result<usize> {
+    check_format(ihdr)?;
+    if !BITS_PER_SAMPLE.contains(&bits) {
+        return Err(LsbError::BadBitsPerSample(bits).into());
+    }
+    Ok(carrier_count(ihdr).saturating_sub(HEADER_SIZE * 8) * bits as usize / 8)
+}
+
+/// Sets the low `bits` bits of the bytes at `carriers` to the bits of `data`, most significant
+/// first.
+fn write_bits(pixels: &mut [u8], carriers: impl Iterator<Item = usize>, data: &[u8], bits: u8) {
+    let mask = (1 << bits) - 1;
+    let values = data.iter().flat_map(|byte| {
+        (0..8 / bits)
+            .rev()
+            .map(move |group| (byte >> (group * bits)) & mask)
+    });
+    for (value, index) in values.zip(carriers) {
+        pixels[index] = (pixels[index] & !mask) | value;
+    }
+}
+
+/// Reverses `write_bits`, reading `length` bytes. `carriers` has to yield enough positions.
+fn read_bits(
+    pixels: &[u8],
+    mut carriers: impl Iterator<Item = usize>,
+    length: usize,
+    bits: u8,
+) -> Vec<u8> {
+    let mask = (1 << bits) - 1;
+    (0..length)
+        .map(|_| {
+            carriers
+                .by_ref()
+                .take(8 / bits as usize)
+                .fold(0, |byte, index| (byte << bits) | (pixels[index] & mask))
+        })
+        .collect()
+}
+
+/// Returns the `IHDR` of `png` if its pixels can carry a message.
+fn carrier_ihdr(png: &Png) -> Result<Ihdr> {
+    let ihdr = png.ihdr()?;
+    check_format(&ihdr)?;
+    Ok(ihdr)
+}
+
+fn check_format(ihdr: &Ihdr) -> Result<()> {
+    // Flipping the low bit of a palette index or of a sample with fewer than 8 bits changes
+    // the pixel beyond recognition.
+    if ihdr.color_type == ColorType::Indexed || ihdr.bit_depth < 8 {
+        return Err(LsbError::UnsupportedFormat {
+            bit_depth: ihdr.bit_depth,
+            color_type: ihdr.color_type,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Number of samples of a pixel that carry the message, which is all but alpha.
+fn color_channels(ihdr: &Ihdr) -> usize {
+    match ihdr.color_type {
+        ColorType::GrayscaleAlpha | ColorType::Rgba => ihdr.color_type.channels() - 1,
+        _ => ihdr.color_type.channels(),
+    }
+}
+
+/// Number of positions `carrier_bytes` yields.
+fn carrier_count(ihdr: &Ihdr) -> usize {
+    ihdr.width as usize * ihdr.height as usize * color_channels(ihdr)
+}
+
+/// Positions in the unfiltered pixel data of the bytes whose low bits carry the message: the
+/// least significant byte of every color sample, in order.
+fn carrier_bytes(ihdr: &Ihdr) -> impl Iterator<Item = usize> {
+    let channels = ihdr.color_type.channels();
+    let has_alpha = color_channels(ihdr) < channels;
+    let sample_size = ihdr.bit_depth as usize / 8;
+    let row_size = ihdr.row_size(ihdr.width);
+    let samples = ihdr.width as usize * channels;
+
+    (0..ihdr.height as usize).flat_map(move |row| {
+        (0..samples)
+            .filter(move |sample| !has_alpha || sample % channels != channels - 1)
+            .map(move |sample| row * row_size + sample * sample_size + sample_size - 1)
+    })
+}
+
+/// Unfilters the image data of `png`, with the pixels as a full raster even if it is
+/// interlaced.
+fn decode(png: &Png) -> Result<Unfiltered> {
+    let image_data = ImageData::from_png(png)?;
+    let unfiltered = filter::unfilter(&image_data)?;
+    Ok(Unfiltered {
+        pixels: adam7::deinterlace(image_data.ihdr(), &unfiltered.pixels)?,
+        ..unfiltered
+    })
+}
+
+#[derive(Debug)]
+pub enum LsbError {
+    UnsupportedFormat {
+        bit_depth: u8,
+        color_type: ColorType,
+    },
+    BadBitsPerSample(u8),
+    Corrupt,
+    /// The pixels hold a single message, so there is nothing to list or pick by index.
+    SingleMessage,
+}
+
+impl std::error::Error for LsbError {}
+
+impl Display for LsbError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LsbError::UnsupportedFormat {
+                bit_depth,
+                color_type,
+            } => write!(
+                f,
+                "cannot hide data in the pixels of {}-bit {} images",
+                bit_depth, color_type
+            ),
+            LsbError::BadBitsPerSample(bits) => {
+                write!(f, "cannot use {} bits per sample, only 1, 2 or 4", bits)
+            }
+            LsbError::Corrupt => write!(f, "the message hidden in the pixels is corrupt"),
+            LsbError::SingleMessage => write!(
+                f,
+                "the pixels hold a single message, which cannot be listed, picked by index or \
+                 reported in another format"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::filter::FilterType;
+    use crate::idat::IDAT;
+    use crate::ihdr::Interlace;
+    use crate::png::sample;
+    use crate::Error;
+    use std::str::FromStr;
+
+    fn chunk_type() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    /// An image of arbitrary pixels whose rows cycle through every filter type.
+    fn filtered_png(bit_depth: u8, color_type: ColorType, width: u32, height: u32) -> Png {
+        encode(Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlace: Interlace::None,
+        })
+    }
+
+    fn encode(ihdr: Ihdr) -> Png {
+        let rows = adam7::sub_images(&ihdr);
+        let pixels: Vec<u8> = (0..rows
+            .iter()
+            .map(|&(width, height)| ihdr.row_size(width) * height as usize)
+            .sum())
+            .map(|i: usize| (i * 37 % 251) as u8)
+            .collect();
+        let filter_types: Vec<FilterType> =
+            (0..rows.iter().map(|&(_, height)| height as usize).sum())
+                .map(|row: usize| FilterType::ALL[row % 5])
+                .collect();
+        let image_data = filter::filter_with(&ihdr, &pixels, &filter_types).unwrap();
+
+        let mut chunks = vec![Chunk::new(
+            ChunkType::from_str("IHDR").unwrap(),
+            ihdr.as_bytes(),
+        )];
+        chunks.extend(image_data.to_chunks(64).unwrap());
+        chunks.push(Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]));
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_embed_and_extract() {
+        let mut png = sample();
+        let original = decode(&png).unwrap();
+        let max_chunk_size = idat::max_chunk_size(&png);
+
+        embed(&mut png, &chunk_type(), b"hidden in plain sight", 1).unwrap();
+        let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert_eq!(
+            extract(&png, "ruSt").unwrap(),
+            Some(b"hidden in plain sight".to_vec())
+        );
+        assert_eq!(extract(&png, "ruSx").unwrap(), None);
+        assert!(idat::max_chunk_size(&png) <= max_chunk_size);
+
+        // Only the lowest bit of any byte may have changed.
+        let changed = decode(&png).unwrap();
+        assert_eq!(changed.filter_types, original.filter_types);
+        assert!(changed
+            .pixels
+            .iter()
+            .zip(&original.pixels)
+            .all(|(a, b)| a >> 1 == b >> 1));
+    }
+
+    #[test]
+    fn test_no_message() {
+        assert_eq!(extract(&sample(), "ruSt").unwrap(), None);
+    }
+
+    #[test]
+    fn test_every_filter_and_format() {
+        for (bit_depth, color_type) in [
+            (8, ColorType::Rgb),
+            (8, ColorType::Rgba),
+            (16, ColorType::Grayscale),
+            (16, ColorType::GrayscaleAlpha),
+        ] {
+            let png = filtered_png(bit_depth, color_type, 17, 10);
+            let original = decode(&png).unwrap();
+
+            for bits in BITS_PER_SAMPLE {
+                let mut png = filtered_png(bit_depth, color_type, 17, 10);
+                embed(&mut png, &chunk_type(), b"Hello", bits).unwrap();
+                assert_eq!(extract(&png, "ruSt").unwrap(), Some(b"Hello".to_vec()));
+
+                let changed = decode(&png).unwrap();
+                let ihdr = png.ihdr().unwrap();
+                let carriers: Vec<usize> = carrier_bytes(&ihdr).collect();
+                assert_eq!(carriers.len(), carrier_count(&ihdr));
+                for (index, (a, b)) in changed.pixels.iter().zip(&original.pixels).enumerate() {
+                    assert!(a == b || (carriers.contains(&index) && a >> bits == b >> bits));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_interlaced() {
+        let ihdr = Ihdr {
+            width: 13,
+            height: 11,
+            bit_depth: 8,
+            color_type: ColorType::Rgba,
+            interlace: Interlace::Adam7,
+        };
+        let original = decode(&encode(ihdr)).unwrap();
+
+        let mut png = encode(ihdr);
+        embed(&mut png, &chunk_type(), b"Hello, Adam7", 2).unwrap();
+        let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert_eq!(png.ihdr().unwrap(), ihdr);
+        assert_eq!(
+            extract(&png, "ruSt").unwrap(),
+            Some(b"Hello, Adam7".to_vec())
+        );
+
+        // Only the carriers of the full raster may have changed, not the bytes at the same
+        // positions in the passes.
+        let changed = decode(&png).unwrap();
+        assert_eq!(changed.filter_types, original.filter_types);
+        let carriers: Vec<usize> = carrier_bytes(&ihdr).collect();
+        for (index, (a, b)) in changed.pixels.iter().zip(&original.pixels).enumerate() {
+            assert!(a == b || (carriers.contains(&index) && a >> 2 == b >> 2));
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        let ihdr = sample().ihdr().unwrap();
+        // 500x500 RGBA has 750000 color samples, 104 of which carry the header.
+        assert_eq!(capacity(&ihdr, 1).unwrap(), 93737);
+        assert_eq!(capacity(&ihdr, 2).unwrap(), 187474);
+        assert_eq!(capacity(&ihdr, 4).unwrap(), 374948);
+        assert!(matches!(
+            capacity(&ihdr, 3),
+            Err(Error::Lsb(LsbError::BadBitsPerSample(3)))
+        ));
+
+        let mut png = sample();
+        let payload = vec![0xA5; 93737];
+        embed(&mut png, &chunk_type(), &payload, 1).unwrap();
+        assert_eq!(extract(&png, "ruSt").unwrap(), Some(payload));
+    }
+
+    #[test]
+    fn test_too_large() {
+        let mut png = filtered_png(8, ColorType::Rgb, 8, 8);
+        // 192 color samples hold the 13 byte header and 11 bytes of payload.
+        assert!(matches!(
+            embed(&mut png, &chunk_type(), &[0; 12], 1),
+            Err(Error::Capacity(CapacityError::TooLarge {
+                needed: 12,
+                capacity: 11,
+                ..
+            }))
+        ));
+        assert!(embed(&mut png, &chunk_type(), &[0; 11], 1).is_ok());
+
+        let mut png = filtered_png(8, ColorType::Rgb, 4, 4);
+        assert!(embed(&mut png, &chunk_type(), b"", 4).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_images() {
+        let mut png = filtered_png(8, ColorType::Indexed, 4, 4);
+        assert!(matches!(
+            embed(&mut png, &chunk_type(), b"Hello", 1),
+            Err(Error::Lsb(LsbError::UnsupportedFormat { .. }))
+        ));
+
+        let png = filtered_png(4, ColorType::Grayscale, 4, 4);
+        assert!(matches!(
+            extract(&png, "ruSt"),
+            Err(Error::Lsb(LsbError::UnsupportedFormat { .. }))
+        ));
+
+        // The size IHDR claims is checked against the image data before anything is allocated
+        // for it.
+        let mut png = filtered_png(8, ColorType::Rgb, 4, 4);
+        let ihdr = Ihdr {
+            width: 60_000,
+            height: 60_000,
+            ..png.ihdr().unwrap()
+        };
+        png.remove_chunk("IHDR").unwrap();
+        png.insert_chunk(
+            0,
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr.as_bytes()),
+        )
+        .unwrap();
+        assert!(matches!(
+            extract(&png, "ruSt"),
+            Err(Error::Idat(IdatError::BadSize { .. }))
+        ));
+        assert!(matches!(
+            embed(&mut png, &chunk_type(), b"Hello", 1),
+            Err(Error::Idat(IdatError::BadSize { .. }))
+        ));
+
+        png.remove_chunk(IDAT).unwrap();
+        png.remove_chunk("IHDR").unwrap();
+        assert!(matches!(
+            embed(&mut png, &chunk_type(), b"Hello", 1),
+            Err(Error::Ihdr(_))
+        ));
+    }
+}
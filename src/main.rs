@@ -1,12 +1,11 @@
 mod args;
-mod chunk;
-mod chunk_type;
 mod commands;
-mod png;
 
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
+use std::process;
 
-fn main() -> Result<()> {
-    args::run()
+fn main() {
+    if let Err(err) = args::run() {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,1048 @@
+use crate::capacity::{CapacityError, Embedding};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::compression::{self, Compression};
+use crate::crypto::{self, CryptoError, Decryption, Encryption, PrivateKey, PublicKey};
+use crate::envelope::Envelope;
+use crate::fragment::{self, Fragment, FragmentError};
+use crate::lsb;
+use crate::png::{ChunkPosition, Diagnostic, Png, PngError};
+use crate::png_ref::PngRef;
+use crate::sealed::{self, Content, Kind, SealedError};
+use crate::signature::{self, Signature, SignatureError, SigningKey, Verification, VerifyingKey};
+use crate::stream::{ChunkReader, ChunkWriter};
+use crate::trailer;
+use crate::Result;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Prepares `content` for embedding, compressing it when `compression` is given and then
+/// encrypting it when `encryption` is given. Every layer is tagged with its `sealed::Kind`.
+pub fn seal_message(
+    content: &Content,
+    compression: Option<Compression>,
+    encryption: Option<&Encryption>,
+) -> Result<Vec<u8>> {
+    let mut data = match content {
+        Content::Message(message) => sealed::wrap(Kind::Message, message),
+        Content::File(envelope) => sealed::wrap(Kind::File, &envelope.as_bytes()?),
+    };
+    if let Some(compression) = compression {
+        data = sealed::wrap(
+            Kind::Compressed,
+            &compression::compress(&data, compression)?,
+        );
+    }
+    if let Some(encryption) = encryption {
+        data = sealed::wrap(Kind::Encrypted, &crypto::encrypt_with(&data, encryption)?);
+    }
+    Ok(data)
+}
+
+/// Reverses `seal_message`, returning the message as text.
+pub fn open_message(data: &[u8], decryption: Option<&Decryption>) -> Result<String> {
+    match open_payload(data, decryption)? {
+        Content::Message(message) => into_string(message),
+        Content::File(_) => Err(SealedError::Misplaced(Kind::File).into()),
+    }
+}
+
+/// Reverses `seal_message`, returning what was sealed. `decryption` is only needed if the
+/// payload is encrypted. A payload that was not sealed is a message stored as it is.
+pub fn open_payload(data: &[u8], decryption: Option<&Decryption>) -> Result<Content> {
+    if !sealed::is_sealed(data) {
+        return Ok(Content::Message(data.to_vec()));
+    }
+    let (mut kind, mut body) = sealed::unwrap(data)?;
+
+    let decrypted;
+    if kind == Kind::Encrypted {
+        decrypted = match (decryption, crypto::scheme(body)) {
+            (Some(decryption), _) => crypto::decrypt_with(body, decryption)?,
+            (None, Some(crypto::SCHEME_PASSPHRASE)) => {
+                return Err(CryptoError::PassphraseRequired.into())
+            }
+            (None, _) => return Err(CryptoError::PrivateKeyRequired.into()),
+        };
+        (kind, body) = sealed::unwrap(&decrypted)?;
+    }
+
+    let decompressed;
+    if kind == Kind::Compressed {
+        decompressed = compression::decompress(body)?;
+        (kind, body) = sealed::unwrap(&decompressed)?;
+    }
+
+    match kind {
+        Kind::Message => Ok(Content::Message(body.to_vec())),
+        Kind::File => Ok(Content::File(Envelope::try_from(body)?)),
+        kind => Err(SealedError::Misplaced(kind).into()),
+    }
+}
+
+/// Hides `message` in a new chunk of type `chunk_type`, placed at `position`, and returns the
+/// index of that chunk. The message is sealed with `seal_message`, as the `png-msg` binary does.
+pub fn encode<M: AsRef<[u8]>>(
+    png: &mut Png,
+    chunk_type: &str,
+    message: M,
+    position: &ChunkPosition,
+) -> Result<usize> {
+    let data = seal_message(&Content::Message(message.as_ref().to_vec()), None, None)?;
+    encode_fragments(png, chunk_type, &[data], position)
+}
+
+/// Hides a payload that was split with `fragment::split` in consecutive chunks of type
+/// `chunk_type`, placed at `position`, and returns the index of the first of them. The
+/// fragments are stored as they are, so the payload should come from `seal_message`.
+pub fn encode_fragments<M: AsRef<[u8]>>(
+    png: &mut Png,
+    chunk_type: &str,
+    fragments: &[M],
+    position: &ChunkPosition,
+) -> Result<usize> {
+    let chunks = message_chunks(chunk_type, fragments)?;
+    png.place_chunks(position, chunks)
+}
+
+/// Hides `payload` after `IEND` instead of in a chunk, recording `chunk_type` so that it can
+/// be decoded and removed like a message stored in a chunk. Fails rather than overwrite
+/// trailing data that is already there.
+pub fn encode_trailing(png: &mut Png, chunk_type: &str, payload: &[u8]) -> Result<()> {
+    if !png.trailing_data().is_empty() {
+        return Err(PngError::TrailingDataPresent(png.trailing_data().len()).into());
+    }
+
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    png.set_trailing_data(trailer::wrap(&chunk_type, payload));
+    Ok(())
+}
+
+/// Hides `payload` in the `bits` low bits of the pixels of the image instead of in a chunk,
+/// recording `chunk_type` so that it can be decoded with `payload_lsb`. See `lsb::embed` for
+/// which images can carry a message.
+pub fn encode_lsb(png: &mut Png, chunk_type: &str, payload: &[u8], bits: u8) -> Result<()> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    lsb::embed(png, &chunk_type, payload, bits)
+}
+
+/// Returns the message stored under `chunk_type` by `encode_lsb`, if there is one.
+pub fn payload_lsb(png: &Png, chunk_type: &str) -> Result<Option<Vec<u8>>> {
+    lsb::extract(png, chunk_type)
+}
+
+/// Returns the message stored under `chunk_type`, if there is one, opened with
+/// `open_message`. Encrypted messages need `payload` and `open_message` with a key instead.
+pub fn decode(png: &Png, chunk_type: &str) -> Result<Option<String>> {
+    match payload(png, chunk_type)? {
+        Some(payload) => Ok(Some(open_message(&payload, None)?)),
+        None => Ok(None),
+    }
+}
+
+/// Zero-copy version of `decode` over a borrowed PNG. A message that was split across several
+/// chunks, compressed or encrypted cannot be borrowed in one piece, so use `payload_ref` for
+/// those.
+pub fn decode_ref<'a>(png: &PngRef<'a>, chunk_type: &str) -> Result<Option<&'a str>> {
+    match png.chunk_by_type(chunk_type)? {
+        Some(chunk) if fragment::is_fragment(chunk.data()) => Err(FragmentError::Fragmented.into()),
+        Some(chunk) => Ok(Some(borrow_message(chunk.data())?)),
+        None => match trailer::unwrap(png.trailing_data()?, chunk_type) {
+            Some(payload) => Ok(Some(borrow_message(payload)?)),
+            None => Ok(None),
+        },
+    }
+}
+
+/// Borrows the text of a payload holding a message that is neither compressed nor encrypted.
+fn borrow_message(data: &[u8]) -> Result<&str> {
+    if !sealed::is_sealed(data) {
+        return Ok(std::str::from_utf8(data)?);
+    }
+    match sealed::unwrap(data)? {
+        (Kind::Message, body) => Ok(std::str::from_utf8(body)?),
+        (kind, _) => Err(SealedError::Unborrowable(kind).into()),
+    }
+}
+
+/// Returns the data of the first chunk of type `chunk_type`, reassembled from the following
+/// chunks of that type if it was split with `fragment::split`. Without such a chunk, a message
+/// stored after `IEND` by `encode_trailing` is returned instead.
+pub fn payload(png: &Png, chunk_type: &str) -> Result<Option<Vec<u8>>> {
+    let parts: Vec<&[u8]> = png.chunks_by_type(chunk_type).map(Chunk::data).collect();
+    match join(&parts)? {
+        Some(payload) => Ok(Some(payload)),
+        None => Ok(trailer::unwrap(png.trailing_data(), chunk_type).map(<[u8]>::to_vec)),
+    }
+}
+
+/// Borrowed version of `payload`. Stops reading at the chunk if the message is not fragmented.
+pub fn payload_ref(png: &PngRef, chunk_type: &str) -> Result<Option<Vec<u8>>> {
+    let mut parts = Vec::new();
+    for chunk in png.chunks_by_type(chunk_type) {
+        parts.push(chunk?.data());
+        if !fragment::is_fragment(parts[0]) {
+            break;
+        }
+    }
+    match join(&parts)? {
+        Some(payload) => Ok(Some(payload)),
+        None => Ok(trailer::unwrap(png.trailing_data()?, chunk_type).map(<[u8]>::to_vec)),
+    }
+}
+
+/// A message found under a chunk type, reassembled if it was split across several chunks.
+/// `index` is the position in the PNG of the chunk the message starts in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredMessage {
+    pub index: usize,
+    pub payload: Vec<u8>,
+}
+
+/// Returns every message stored under `chunk_type`, in the order they start in the file.
+pub fn payloads(png: &Png, chunk_type: &str) -> Result<Vec<StoredMessage>> {
+    let parts = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| chunk.chunk_type().to_string() == chunk_type)
+        .map(|(index, chunk)| (index, chunk.data()));
+    collect_messages(parts)
+}
+
+/// Borrowed version of `payloads`.
+pub fn payloads_ref(png: &PngRef, chunk_type: &str) -> Result<Vec<StoredMessage>> {
+    let mut parts = Vec::new();
+    for (index, chunk) in png.chunks().enumerate() {
+        let chunk = chunk?;
+        if chunk.chunk_type().to_string() == chunk_type {
+            parts.push((index, chunk.data()));
+        }
+    }
+    collect_messages(parts)
+}
+
+/// Groups the data of chunks that share a type into messages, given each chunk's position in
+/// the PNG. Fragments are matched up with the rest of their message wherever they are.
+pub fn collect_messages<'a, I>(parts: I) -> Result<Vec<StoredMessage>>
+where
+    I: IntoIterator<Item = (usize, &'a [u8])>,
+{
+    let mut groups: Vec<(usize, Vec<&[u8]>)> = Vec::new();
+    for (index, data) in parts {
+        let group = match Fragment::try_from(data) {
+            Ok(fragment) => groups.iter_mut().find(|(_, group)| {
+                Fragment::try_from(group[0]).is_ok_and(|first| first.is_sibling(&fragment))
+            }),
+            Err(_) => None,
+        };
+
+        match group {
+            Some((_, group)) => group.push(data),
+            None => groups.push((index, vec![data])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(index, parts)| {
+            Ok(StoredMessage {
+                index,
+                payload: fragment::join(&parts)?,
+            })
+        })
+        .collect()
+}
+
+/// Removes the first chunk of type `chunk_type`, along with the rest of the message if it was
+/// split across several chunks, and returns the removed chunks in order. Without such a chunk,
+/// a message stored after `IEND` by `encode_trailing` is removed and returned as the chunk it
+/// would otherwise have been stored in.
+pub fn remove(png: &mut Png, chunk_type: &str) -> Result<Vec<Chunk>> {
+    if png.position_of(chunk_type).is_none() {
+        return remove_trailing(png, chunk_type);
+    }
+
+    let mut removed = vec![png.remove_chunk(chunk_type)?];
+    while let Some(index) = png.chunks().iter().position(|chunk| {
+        chunk.chunk_type().to_string() == chunk_type && same_message(&removed[0], chunk)
+    }) {
+        removed.push(png.remove_chunk_at(index)?);
+    }
+    Ok(removed)
+}
+
+/// Removes every chunk of type `chunk_type`, and any message stored after `IEND` under it, and
+/// returns them in order.
+pub fn remove_all(png: &mut Png, chunk_type: &str) -> Result<Vec<Chunk>> {
+    let mut removed = Vec::new();
+    while let Some(index) = png.position_of(chunk_type) {
+        removed.push(png.remove_chunk_at(index)?);
+    }
+    if let Ok(trailing) = remove_trailing(png, chunk_type) {
+        removed.extend(trailing);
+    }
+
+    if removed.is_empty() {
+        return Err(PngError::InvalidChunkType.into());
+    }
+    Ok(removed)
+}
+
+fn remove_trailing(png: &mut Png, chunk_type: &str) -> Result<Vec<Chunk>> {
+    let payload = trailer::unwrap(png.trailing_data(), chunk_type)
+        .map(<[u8]>::to_vec)
+        .ok_or(PngError::InvalidChunkType)?;
+    png.strip_trailing_data();
+    Ok(vec![Chunk::new(ChunkType::from_str(chunk_type)?, payload)])
+}
+
+/// Signs the message whose first chunk is at `index`, as returned by `encode_fragments`, all of
+/// its fragments if it was split, and stores the signature in a companion chunk placed right
+/// after its last chunk. See `Signature::sign` for what `cover_image` does.
+pub fn sign(
+    png: &mut Png,
+    index: usize,
+    key: &SigningKey,
+    signer_name: &str,
+    cover_image: bool,
+) -> Result<()> {
+    let signature = Signature::sign(png, index, key, signer_name, cover_image)?;
+    let last = *signature::message_indices(png, index).last().unwrap();
+    png.insert_chunk(last + 1, signature.to_chunk())
+}
+
+/// Checks the first signature made for a message stored under `chunk_type`, against the
+/// message it was placed after.
+pub fn verify(png: &Png, chunk_type: &str) -> Result<Verification> {
+    match Signature::find(png, chunk_type)? {
+        Some((index, signature)) => Ok(signature.verify(png, index)),
+        None => Err(SignatureError::MissingSignature(chunk_type.to_string()).into()),
+    }
+}
+
+/// Streaming version of `encode`: copies the PNG from `reader` to `writer` one chunk at a time,
+/// inserting the message chunk at `position` on the way.
+pub fn encode_stream<R: Read, W: Write, M: AsRef<[u8]>>(
+    reader: R,
+    writer: W,
+    chunk_type: &str,
+    message: M,
+    position: &ChunkPosition,
+) -> Result<W> {
+    let data = seal_message(&Content::Message(message.as_ref().to_vec()), None, None)?;
+    encode_fragments_stream(reader, writer, chunk_type, &[data], position)
+}
+
+/// Streaming version of `encode_fragments`.
+pub fn encode_fragments_stream<R: Read, W: Write, M: AsRef<[u8]>>(
+    reader: R,
+    writer: W,
+    chunk_type: &str,
+    fragments: &[M],
+    position: &ChunkPosition,
+) -> Result<W> {
+    let mut new_chunks = Some(message_chunks(chunk_type, fragments)?);
+
+    let mut count = 0;
+    let mut reader = ChunkReader::new(reader)?;
+    let mut writer = ChunkWriter::new(writer)?;
+    for (index, chunk) in reader.by_ref().enumerate() {
+        let chunk = chunk?;
+        count += 1;
+        let chunk_type = chunk.chunk_type().to_string();
+
+        let insert_before = match position {
+            ChunkPosition::BeforeIend => chunk_type == Png::IEND,
+            ChunkPosition::Index(target) => index == *target,
+            _ => false,
+        };
+        if insert_before {
+            for new_chunk in new_chunks.take().unwrap_or_default() {
+                writer.write_chunk(&new_chunk)?;
+            }
+        }
+
+        writer.write_chunk(&chunk)?;
+
+        let insert_after = match position {
+            ChunkPosition::AfterIhdr => chunk_type == Png::IHDR,
+            ChunkPosition::After(target) => &chunk_type == target,
+            _ => false,
+        };
+        if insert_after {
+            for new_chunk in new_chunks.take().unwrap_or_default() {
+                writer.write_chunk(&new_chunk)?;
+            }
+        }
+    }
+
+    if let Some(new_chunks) = new_chunks {
+        match position {
+            ChunkPosition::AfterIhdr | ChunkPosition::After(_) => {
+                return Err(PngError::InvalidChunkType.into())
+            }
+            ChunkPosition::Index(index) if *index != count => {
+                return Err(PngError::InvalidChunkIndex(*index).into())
+            }
+            _ => {
+                for new_chunk in new_chunks {
+                    writer.write_chunk(&new_chunk)?;
+                }
+            }
+        }
+    }
+
+    writer.write_trailing_data(reader.trailing_data())?;
+    writer.into_inner()
+}
+
+/// Streaming version of `decode`: stops reading as soon as the chunk is found, unless the
+/// message was split across several chunks.
+pub fn decode_stream<R: Read>(reader: R, chunk_type: &str) -> Result<Option<String>> {
+    let mut chunks = Vec::new();
+    let mut reader = ChunkReader::new(reader)?;
+    for chunk in reader.by_ref() {
+        let chunk = chunk?;
+        if chunk.chunk_type().to_string() != chunk_type {
+            continue;
+        }
+
+        chunks.push(chunk);
+        if !fragment::is_fragment(chunks[0].data()) {
+            break;
+        }
+    }
+
+    let parts: Vec<&[u8]> = chunks.iter().map(Chunk::data).collect();
+    let payload = match join(&parts)? {
+        Some(payload) => Some(payload),
+        None => trailer::unwrap(reader.trailing_data(), chunk_type).map(<[u8]>::to_vec),
+    };
+    payload
+        .map(|payload| open_message(&payload, None))
+        .transpose()
+}
+
+/// Streaming version of `remove`: copies every chunk except the ones that `remove` would take
+/// out.
+pub fn remove_stream<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    chunk_type: &str,
+) -> Result<(Vec<Chunk>, W)> {
+    remove_stream_where(reader, writer, chunk_type, false)
+}
+
+/// Streaming version of `remove_all`.
+pub fn remove_all_stream<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    chunk_type: &str,
+) -> Result<(Vec<Chunk>, W)> {
+    remove_stream_where(reader, writer, chunk_type, true)
+}
+
+fn remove_stream_where<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    chunk_type: &str,
+    all: bool,
+) -> Result<(Vec<Chunk>, W)> {
+    let mut removed: Vec<Chunk> = Vec::new();
+
+    let mut reader = ChunkReader::new(reader)?;
+    let mut writer = ChunkWriter::new(writer)?;
+    for chunk in reader.by_ref() {
+        let chunk = chunk?;
+        let is_message = chunk.chunk_type().to_string() == chunk_type
+            && (all
+                || removed
+                    .first()
+                    .is_none_or(|first| same_message(first, &chunk)));
+
+        if is_message {
+            removed.push(chunk);
+        } else {
+            writer.write_chunk(&chunk)?;
+        }
+    }
+
+    let trailing =
+        trailer::unwrap(reader.trailing_data(), chunk_type).filter(|_| all || removed.is_empty());
+    match trailing {
+        Some(payload) => removed.push(Chunk::new(
+            ChunkType::from_str(chunk_type)?,
+            payload.to_vec(),
+        )),
+        None => writer.write_trailing_data(reader.trailing_data())?,
+    }
+
+    if removed.is_empty() {
+        return Err(PngError::InvalidChunkType.into());
+    }
+    Ok((removed, writer.into_inner()?))
+}
+
+fn message_chunks<M: AsRef<[u8]>>(chunk_type: &str, fragments: &[M]) -> Result<Vec<Chunk>> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    fragments
+        .iter()
+        .map(|fragment| {
+            let fragment = fragment.as_ref();
+            if fragment.len() > Chunk::MAX_DATA_LENGTH {
+                return Err(CapacityError::too_large(
+                    Embedding::Chunk,
+                    fragment,
+                    Chunk::MAX_DATA_LENGTH,
+                )
+                .into());
+            }
+            Ok(Chunk::new(chunk_type, fragment.to_vec()))
+        })
+        .collect()
+}
+
+fn join(parts: &[&[u8]]) -> Result<Option<Vec<u8>>> {
+    if parts.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(fragment::join(parts)?))
+}
+
+/// Whether `chunk` holds another fragment of the message that starts in `first`.
+fn same_message(first: &Chunk, chunk: &Chunk) -> bool {
+    fragment::same_payload(first.data(), chunk.data())
+}
+
+fn into_string(data: Vec<u8>) -> Result<String> {
+    Ok(String::from_utf8(data).map_err(|err| err.utf8_error())?)
+}
+
+pub fn read_png<P: AsRef<Path>>(path: P) -> Result<Png> {
+    let file_contents = fs::read(path)?;
+    let png = Png::try_from(file_contents.as_slice())?;
+    Ok(png)
+}
+
+/// Reads a PNG with `Png::parse_lenient`, recovering whatever chunks can be salvaged.
+pub fn read_png_lenient<P: AsRef<Path>>(path: P) -> Result<(Png, Vec<Diagnostic>)> {
+    let file_contents = fs::read(path)?;
+    Png::parse_lenient(file_contents.as_slice())
+}
+
+/// Reads a passphrase from the file at `path`, leaving out the line break it ends with.
+pub fn read_passphrase<P: AsRef<Path>>(path: P) -> Result<String> {
+    let passphrase = fs::read_to_string(path)?;
+    let passphrase = passphrase.strip_suffix('\n').unwrap_or(&passphrase);
+    let passphrase = passphrase.strip_suffix('\r').unwrap_or(passphrase);
+    Ok(passphrase.to_string())
+}
+
+pub fn read_public_key<P: AsRef<Path>>(path: P) -> Result<PublicKey> {
+    PublicKey::from_str(&fs::read_to_string(path)?)
+}
+
+pub fn read_private_key<P: AsRef<Path>>(path: P) -> Result<PrivateKey> {
+    PrivateKey::from_str(&fs::read_to_string(path)?)
+}
+
+pub fn read_signing_key<P: AsRef<Path>>(path: P) -> Result<SigningKey> {
+    SigningKey::from_str(&fs::read_to_string(path)?)
+}
+
+pub fn read_verifying_key<P: AsRef<Path>>(path: P) -> Result<VerifyingKey> {
+    VerifyingKey::from_str(&fs::read_to_string(path)?)
+}
+
+pub fn write_png<P: AsRef<Path>>(path: P, png: &Png) -> Result<()> {
+    fs::write(path, png.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn testing_png() -> Png {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ];
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let mut png = testing_png();
+        encode(&mut png, "RuSt", "Hello", &ChunkPosition::BeforeIend).unwrap();
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some(String::from("Hello")));
+        assert_eq!(png.position_of("RuSt"), Some(1));
+
+        // The message is sealed the way the binary seals it, and read back the same way.
+        let stored = payload(&png, "RuSt").unwrap().unwrap();
+        assert_eq!(
+            open_payload(&stored, None).unwrap(),
+            Content::Message(b"Hello".to_vec())
+        );
+        let mut png = testing_png();
+        let content = Content::Message(b"Hello, hello, hello".to_vec());
+        let data = seal_message(&content, Some(Compression::Deflate), None).unwrap();
+        encode_fragments(&mut png, "RuSt", &[data], &ChunkPosition::BeforeIend).unwrap();
+        assert_eq!(
+            decode(&png, "RuSt").unwrap(),
+            Some(String::from("Hello, hello, hello"))
+        );
+    }
+
+    #[test]
+    fn test_decode_ref() {
+        let mut png = testing_png();
+        encode(&mut png, "RuSt", "Hello", &ChunkPosition::BeforeIend).unwrap();
+        let bytes = png.as_bytes();
+        let png = PngRef::try_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(decode_ref(&png, "RuSt").unwrap(), Some("Hello"));
+        assert_eq!(decode_ref(&png, "NoPe").unwrap(), None);
+
+        let mut png = testing_png();
+        let content = Content::Message(b"Hello".to_vec());
+        let data = seal_message(&content, Some(Compression::Deflate), None).unwrap();
+        encode_fragments(&mut png, "RuSt", &[data], &ChunkPosition::BeforeIend).unwrap();
+        let bytes = png.as_bytes();
+        let png = PngRef::try_from(bytes.as_slice()).unwrap();
+        assert!(matches!(
+            decode_ref(&png, "RuSt"),
+            Err(Error::Sealed(SealedError::Unborrowable(Kind::Compressed)))
+        ));
+    }
+
+    #[test]
+    fn test_seal_and_open_message() {
+        let hello = Content::Message(b"Hello".to_vec());
+        let data = seal_message(&hello, None, None).unwrap();
+        assert_eq!(data, b"PMSE\x01Hello");
+        assert_eq!(open_message(&data, None).unwrap(), "Hello");
+
+        let data = seal_message(&hello, None, Some(&Encryption::Passphrase("hunter2"))).unwrap();
+        assert!(sealed::is_encrypted(&data));
+        let decryption = Decryption::Passphrase("hunter2");
+        assert_eq!(open_message(&data, Some(&decryption)).unwrap(), "Hello");
+        let decryption = Decryption::Passphrase("hunter3");
+        assert!(open_message(&data, Some(&decryption)).is_err());
+        assert!(matches!(
+            open_message(&data, None),
+            Err(Error::Crypto(CryptoError::PassphraseRequired))
+        ));
+
+        let private_key = PrivateKey::generate();
+        let public_key = private_key.public_key();
+        let data = seal_message(&hello, None, Some(&Encryption::PublicKey(&public_key))).unwrap();
+        let decryption = Decryption::PrivateKey(&private_key);
+        assert_eq!(open_message(&data, Some(&decryption)).unwrap(), "Hello");
+        assert!(matches!(
+            open_message(&data, None),
+            Err(Error::Crypto(CryptoError::PrivateKeyRequired))
+        ));
+    }
+
+    #[test]
+    fn test_seal_and_open_compressed_message() {
+        let message = "Hello, hello, hello, hello, hello!";
+        let content = Content::Message(message.as_bytes().to_vec());
+
+        let data = seal_message(&content, Some(Compression::Deflate), None).unwrap();
+        assert_eq!(sealed::kind(&data), Some(Kind::Compressed));
+        assert_eq!(open_message(&data, None).unwrap(), message);
+
+        let encryption = Encryption::Passphrase("hunter2");
+        let data = seal_message(&content, Some(Compression::Zstd), Some(&encryption)).unwrap();
+        assert!(sealed::is_encrypted(&data));
+        let decryption = Decryption::Passphrase("hunter2");
+        assert_eq!(open_message(&data, Some(&decryption)).unwrap(), message);
+    }
+
+    #[test]
+    fn test_seal_and_open_envelope() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let envelope = Envelope::new(Some("blob.bin".to_string()), "image/png".to_string(), data);
+
+        let content = Content::File(envelope);
+        let sealed = seal_message(&content, Some(Compression::Deflate), None).unwrap();
+        assert!(open_message(&sealed, None).is_err());
+        assert_eq!(open_payload(&sealed, None).unwrap(), content);
+    }
+
+    #[test]
+    fn test_open_message_that_looks_like_a_header() {
+        // Plain messages are tagged, so their content is never mistaken for a layer.
+        for message in [
+            "PMSG\x01Hello",
+            "PMCZ\x01Hello",
+            "PMFE\x01Hello",
+            "PMFR\x01Hello",
+        ] {
+            let content = Content::Message(message.as_bytes().to_vec());
+            let data = seal_message(&content, None, None).unwrap();
+            assert!(!fragment::is_fragment(&data));
+            assert_eq!(open_message(&data, None).unwrap(), message);
+        }
+
+        // Layers can only be nested in the order `seal_message` puts them in.
+        let compressed =
+            compression::compress(&sealed::wrap(Kind::Message, b"Hello"), Compression::Deflate)
+                .unwrap();
+        let encrypted =
+            crypto::encrypt(&sealed::wrap(Kind::Compressed, &compressed), "hunter2").unwrap();
+        let data = sealed::wrap(
+            Kind::Compressed,
+            &compression::compress(
+                &sealed::wrap(Kind::Encrypted, &encrypted),
+                Compression::Zstd,
+            )
+            .unwrap(),
+        );
+        assert!(matches!(
+            open_payload(&data, Some(&Decryption::Passphrase("hunter2"))),
+            Err(Error::Sealed(SealedError::Misplaced(Kind::Encrypted)))
+        ));
+        assert!(matches!(
+            open_payload(b"PMSE\x09Hello", None),
+            Err(Error::Sealed(SealedError::UnknownKind(9)))
+        ));
+    }
+
+    #[test]
+    fn test_open_unsealed_message() {
+        // Messages written before payloads were sealed are stored as they are.
+        let mut png = Png::try_from(&include_bytes!("../sample-out.png")[..]).unwrap();
+        let messages = payloads(&png, "ruSt").unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            open_message(&messages[0].payload, None).unwrap(),
+            "my secret message"
+        );
+        assert_eq!(open_message(b"", None).unwrap(), "");
+
+        let removed = remove(&mut png, "ruSt").unwrap();
+        assert_eq!(removed[0].data(), b"my secret message");
+        assert_eq!(png.position_of("ruSt"), None);
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::generate();
+        let mut png = testing_png();
+        let index = encode(&mut png, "RuSt", "Hello", &ChunkPosition::BeforeIend).unwrap();
+        assert!(verify(&png, "RuSt").is_err());
+
+        sign(&mut png, index, &key, "alice", true).unwrap();
+        assert_eq!(png.position_of("siGN"), Some(2));
+
+        let verification = verify(&png, "RuSt").unwrap();
+        assert!(verification.is_valid());
+        assert_eq!(verification.signer, key.verifying_key());
+    }
+
+    #[test]
+    fn test_sign_after_older_message() {
+        let key = SigningKey::generate();
+        let mut png = testing_png();
+        encode(&mut png, "RuSt", "old message", &ChunkPosition::BeforeIend).unwrap();
+        let fragments = fragment::split(b"new message, in pieces", 30).unwrap();
+        let index =
+            encode_fragments(&mut png, "RuSt", &fragments, &ChunkPosition::BeforeIend).unwrap();
+        assert_eq!(index, 2);
+
+        // The signature goes after the message it signs and covers it rather than the old one.
+        sign(&mut png, index, &key, "", false).unwrap();
+        assert_eq!(png.position_of("siGN"), Some(2 + fragments.len()));
+        assert!(verify(&png, "RuSt").unwrap().is_valid());
+
+        let old = png.remove_chunk_at(1).unwrap();
+        assert!(verify(&png, "RuSt").unwrap().is_valid());
+        let new = png.remove_chunk_at(1).unwrap();
+        png.insert_chunk(1, old).unwrap();
+        png.insert_chunk(2, Chunk::new(*new.chunk_type(), b"forged".to_vec()))
+            .unwrap();
+        assert!(!verify(&png, "RuSt").unwrap().message_valid);
+    }
+
+    #[test]
+    fn test_decode_missing() {
+        let png = testing_png();
+        assert_eq!(decode(&png, "RuSt").unwrap(), None);
+    }
+
+    #[test]
+    fn test_encode_stream_matches_encode() {
+        let positions = [
+            ChunkPosition::BeforeIend,
+            ChunkPosition::AfterIhdr,
+            ChunkPosition::After(String::from("IHDR")),
+            ChunkPosition::Index(0),
+            ChunkPosition::Index(2),
+            ChunkPosition::End,
+        ];
+
+        for position in positions.iter() {
+            let mut png = testing_png();
+            let bytes = png.as_bytes();
+            encode(&mut png, "RuSt", "Hello", position).unwrap();
+
+            let streamed =
+                encode_stream(bytes.as_slice(), Vec::new(), "RuSt", "Hello", position).unwrap();
+            assert_eq!(streamed, png.as_bytes(), "position {:?}", position);
+        }
+    }
+
+    #[test]
+    fn test_encode_stream_missing_target() {
+        let bytes = testing_png().as_bytes();
+        let position = ChunkPosition::After(String::from("gAMA"));
+        assert!(encode_stream(bytes.as_slice(), Vec::new(), "RuSt", "Hello", &position).is_err());
+        let position = ChunkPosition::Index(3);
+        assert!(encode_stream(bytes.as_slice(), Vec::new(), "RuSt", "Hello", &position).is_err());
+    }
+
+    #[test]
+    fn test_decode_and_remove_stream() {
+        let mut png = testing_png();
+        encode(&mut png, "RuSt", "Hello", &ChunkPosition::BeforeIend).unwrap();
+        let bytes = png.as_bytes();
+
+        let message = decode_stream(bytes.as_slice(), "RuSt").unwrap();
+        assert_eq!(message, Some(String::from("Hello")));
+        assert_eq!(decode_stream(bytes.as_slice(), "NoPe").unwrap(), None);
+
+        let (chunks, written) = remove_stream(bytes.as_slice(), Vec::new(), "RuSt").unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(open_message(chunks[0].data(), None).unwrap(), "Hello");
+        assert_eq!(written, testing_png().as_bytes());
+        assert!(remove_stream(written.as_slice(), Vec::new(), "RuSt").is_err());
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut png = testing_png();
+        encode(&mut png, "RuSt", "Hello", &ChunkPosition::BeforeIend).unwrap();
+        let removed = remove(&mut png, "RuSt").unwrap();
+        assert_eq!(open_message(removed[0].data(), None).unwrap(), "Hello");
+        assert!(png.chunk_by_type("RuSt").is_none());
+        assert!(remove(&mut png, "RuSt").is_err());
+    }
+
+    #[test]
+    fn test_fragmented_message() {
+        let message = "Hello, this message is too long for a single chunk!";
+        let fragments = fragment::split(message.as_bytes(), 34).unwrap();
+        assert_eq!(fragments.len(), 4);
+
+        let mut png = testing_png();
+        encode(&mut png, "RuSt", "Other", &ChunkPosition::AfterIhdr).unwrap();
+        encode_fragments(&mut png, "RuSt", &fragments, &ChunkPosition::BeforeIend).unwrap();
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some(String::from("Other")));
+
+        remove(&mut png, "RuSt").unwrap();
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some(message.to_string()));
+
+        let bytes = png.as_bytes();
+        assert_eq!(
+            decode_stream(bytes.as_slice(), "RuSt").unwrap(),
+            Some(message.to_string())
+        );
+        let png_ref = PngRef::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(
+            payload_ref(&png_ref, "RuSt").unwrap(),
+            Some(message.as_bytes().to_vec())
+        );
+        assert!(matches!(
+            decode_ref(&png_ref, "RuSt"),
+            Err(Error::Fragment(FragmentError::Fragmented))
+        ));
+
+        let (removed, written) = remove_stream(bytes.as_slice(), Vec::new(), "RuSt").unwrap();
+        assert_eq!(removed.len(), 4);
+        assert_eq!(written, testing_png().as_bytes());
+
+        let mut png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(remove(&mut png, "RuSt").unwrap().len(), 4);
+        assert_eq!(png.as_bytes(), testing_png().as_bytes());
+    }
+
+    #[test]
+    fn test_payloads() {
+        let fragments = fragment::split(&[7; 100], 44).unwrap();
+        let mut png = testing_png();
+        encode(&mut png, "RuSt", "one", &ChunkPosition::BeforeIend).unwrap();
+        encode_fragments(&mut png, "RuSt", &fragments, &ChunkPosition::AfterIhdr).unwrap();
+        encode(&mut png, "RuSt", "three", &ChunkPosition::AfterIhdr).unwrap();
+        encode(&mut png, "OtHr", "other", &ChunkPosition::AfterIhdr).unwrap();
+
+        let expected = vec![
+            StoredMessage {
+                index: 2,
+                payload: sealed::wrap(Kind::Message, b"three"),
+            },
+            StoredMessage {
+                index: 3,
+                payload: vec![7; 100],
+            },
+            StoredMessage {
+                index: 8,
+                payload: sealed::wrap(Kind::Message, b"one"),
+            },
+        ];
+        assert_eq!(payloads(&png, "RuSt").unwrap(), expected);
+
+        let bytes = png.as_bytes();
+        let png_ref = PngRef::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(payloads_ref(&png_ref, "RuSt").unwrap(), expected);
+        assert_eq!(payloads(&png, "NoPe").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_identical_fragmented_messages() {
+        let payload = seal_message(&Content::Message(vec![7; 100]), None, None).unwrap();
+        let mut png = testing_png();
+        for _ in 0..2 {
+            let fragments = fragment::split(&payload, 40).unwrap();
+            encode_fragments(&mut png, "RuSt", &fragments, &ChunkPosition::BeforeIend).unwrap();
+        }
+        let count = fragment::split(&payload, 40).unwrap().len();
+
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some("\x07".repeat(100)));
+        let messages = payloads(&png, "RuSt").unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].index, 1 + count);
+        assert!(messages.iter().all(|message| message.payload == payload));
+
+        assert_eq!(remove(&mut png, "RuSt").unwrap().len(), count);
+        assert_eq!(payloads(&png, "RuSt").unwrap().len(), 1);
+        assert_eq!(remove(&mut png, "RuSt").unwrap().len(), count);
+        assert_eq!(png.as_bytes(), testing_png().as_bytes());
+    }
+
+    #[test]
+    fn test_remove_all() {
+        let fragments = fragment::split(&[7; 100], 44).unwrap();
+        let mut png = testing_png();
+        encode(&mut png, "RuSt", "one", &ChunkPosition::BeforeIend).unwrap();
+        encode_fragments(&mut png, "RuSt", &fragments, &ChunkPosition::BeforeIend).unwrap();
+        let bytes = png.as_bytes();
+
+        let (removed, written) = remove_all_stream(bytes.as_slice(), Vec::new(), "RuSt").unwrap();
+        assert_eq!(removed.len(), 6);
+        assert_eq!(written, testing_png().as_bytes());
+
+        assert_eq!(remove_all(&mut png, "RuSt").unwrap().len(), 6);
+        assert_eq!(png.as_bytes(), testing_png().as_bytes());
+        assert!(remove_all(&mut png, "RuSt").is_err());
+    }
+
+    #[test]
+    fn test_encode_fragments_stream() {
+        let fragments = fragment::split(&[7; 100], 40).unwrap();
+        let position = ChunkPosition::AfterIhdr;
+        let bytes = testing_png().as_bytes();
+
+        let written =
+            encode_fragments_stream(bytes.as_slice(), Vec::new(), "RuSt", &fragments, &position)
+                .unwrap();
+
+        let mut png = testing_png();
+        encode_fragments(&mut png, "RuSt", &fragments, &position).unwrap();
+        assert_eq!(written, png.as_bytes());
+        assert_eq!(payload(&png, "RuSt").unwrap(), Some(vec![7; 100]));
+    }
+
+    #[test]
+    fn test_fragmented_signature() {
+        let key = SigningKey::generate();
+        let fragments = fragment::split(&[7; 100], 40).unwrap();
+        let mut png = testing_png();
+        let index =
+            encode_fragments(&mut png, "RuSt", &fragments, &ChunkPosition::BeforeIend).unwrap();
+        sign(&mut png, index, &key, "", false).unwrap();
+        assert!(verify(&png, "RuSt").unwrap().is_valid());
+
+        // Tamper with the last fragment, which comes right before the signature chunk.
+        let last = png.chunks().len() - 3;
+        let chunks = png.chunks().iter().enumerate().map(|(index, chunk)| {
+            let mut data = chunk.data().to_vec();
+            if index == last {
+                data[fragment::HEADER_SIZE] ^= 0xFF;
+            }
+            Chunk::new(*chunk.chunk_type(), data)
+        });
+
+        let png = Png::from_chunks(chunks.collect());
+        assert!(!verify(&png, "RuSt").unwrap().message_valid);
+    }
+
+    #[test]
+    fn test_encode_trailing() {
+        let mut png = testing_png();
+        encode_trailing(&mut png, "ruSt", b"Hello").unwrap();
+        let bytes = png.as_bytes();
+
+        assert_eq!(decode(&png, "ruSt").unwrap(), Some("Hello".to_string()));
+        assert_eq!(decode(&png, "ruSx").unwrap(), None);
+        let png_ref = PngRef::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(decode_ref(&png_ref, "ruSt").unwrap(), Some("Hello"));
+        assert_eq!(
+            decode_stream(bytes.as_slice(), "ruSt").unwrap(),
+            Some("Hello".to_string())
+        );
+
+        assert!(matches!(
+            encode_trailing(&mut png, "ruSt", b"again"),
+            Err(Error::Png(PngError::TrailingDataPresent(13)))
+        ));
+    }
+
+    #[test]
+    fn test_remove_trailing() {
+        let mut png = testing_png();
+        encode(&mut png, "ruSt", "in a chunk", &ChunkPosition::BeforeIend).unwrap();
+        encode_trailing(&mut png, "ruSt", b"after IEND").unwrap();
+        let bytes = png.as_bytes();
+
+        // The message in a chunk goes first, leaving the trailing data in place.
+        let (removed, written) = remove_stream(bytes.as_slice(), Vec::new(), "ruSt").unwrap();
+        assert_eq!(open_message(removed[0].data(), None).unwrap(), "in a chunk");
+        let (removed, written) = remove_stream(written.as_slice(), Vec::new(), "ruSt").unwrap();
+        assert_eq!(removed[0].data(), b"after IEND");
+        assert_eq!(written, testing_png().as_bytes());
+
+        assert_eq!(remove_all(&mut png, "ruSt").unwrap().len(), 2);
+        assert_eq!(png.as_bytes(), testing_png().as_bytes());
+    }
+
+    #[test]
+    fn test_message_one_byte_over_capacity() {
+        let mut png = crate::png::sample();
+        let embedding = Embedding::Lsb { bits: 4 };
+        let capacity = png.capacity(embedding).unwrap().unwrap();
+
+        let message = Content::Message(vec![b'a'; capacity + 1]);
+        let data = seal_message(&message, None, None).unwrap();
+        match encode_lsb(&mut png, "ruSt", &data, 4) {
+            Err(Error::Capacity(CapacityError::TooLarge {
+                needed,
+                capacity: refused,
+                ..
+            })) => assert_eq!((needed, refused), (capacity + 1, capacity)),
+            other => panic!("expected the message to be refused, got {:?}", other),
+        }
+
+        let message = Content::Message(vec![b'a'; capacity]);
+        let data = seal_message(&message, None, None).unwrap();
+        encode_lsb(&mut png, "ruSt", &data, 4).unwrap();
+        let payload = payload_lsb(&png, "ruSt").unwrap().unwrap();
+        assert_eq!(open_message(&payload, None).unwrap().len(), capacity);
+    }
+}
@@ -1,39 +1,124 @@
 #![allow(unused_variables, dead_code)]
 
 pub use crate::chunk::Chunk;
+use crate::chunk::ChunkParseError;
 pub use crate::chunk_type::ChunkType;
+pub use crate::ihdr::Ihdr;
+use crate::ihdr::IhdrError;
+use crate::trailer::TrailingData;
 use crate::{Error, Result};
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 pub struct Png {
     chunks: Vec<Chunk>,
+    trailing_data: Vec<u8>,
 }
 
 impl Png {
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
+    pub const IHDR: &'static str = "IHDR";
+    pub const IEND: &'static str = "IEND";
+
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
-        Self { chunks }
+        Self {
+            chunks,
+            trailing_data: Vec::new(),
+        }
     }
 
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk)
     }
 
-    pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
-        let pos_to_remove = self
-            .chunks
-            .iter()
-            .position(|chunk| chunk.chunk_type().to_string() == chunk_type);
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) -> Result<()> {
+        if index > self.chunks.len() {
+            return Err(PngError::InvalidChunkIndex(index).into());
+        }
+
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+
+    /// Inserts `chunk` directly before `IEND`, which is where the spec requires ancillary
+    /// chunks to live. Falls back to appending when the image has no `IEND` chunk.
+    pub fn insert_before_iend(&mut self, chunk: Chunk) {
+        match self.position_of(Self::IEND) {
+            Some(pos) => self.chunks.insert(pos, chunk),
+            None => self.chunks.push(chunk),
+        }
+    }
+
+    pub fn insert_after_ihdr(&mut self, chunk: Chunk) -> Result<()> {
+        self.insert_after(Self::IHDR, chunk)
+    }
 
-        if let Some(pos) = pos_to_remove {
+    pub fn insert_after(&mut self, chunk_type: &str, chunk: Chunk) -> Result<()> {
+        match self.position_of(chunk_type) {
+            Some(pos) => self.insert_chunk(pos + 1, chunk),
+            None => Err(PngError::InvalidChunkType.into()),
+        }
+    }
+
+    pub fn place_chunk(&mut self, position: &ChunkPosition, chunk: Chunk) -> Result<()> {
+        match position {
+            ChunkPosition::BeforeIend => {
+                self.insert_before_iend(chunk);
+                Ok(())
+            }
+            ChunkPosition::AfterIhdr => self.insert_after_ihdr(chunk),
+            ChunkPosition::After(chunk_type) => self.insert_after(chunk_type, chunk),
+            ChunkPosition::Index(index) => self.insert_chunk(*index, chunk),
+            ChunkPosition::End => {
+                self.append_chunk(chunk);
+                Ok(())
+            }
+        }
+    }
+
+    /// Inserts `chunks` one after another at `position`, keeping them in order, and returns the
+    /// index of the first of them.
+    pub fn place_chunks(&mut self, position: &ChunkPosition, chunks: Vec<Chunk>) -> Result<usize> {
+        let index = match position {
+            ChunkPosition::BeforeIend => self.position_of(Self::IEND).unwrap_or(self.chunks.len()),
+            ChunkPosition::AfterIhdr => self.index_after(Self::IHDR)?,
+            ChunkPosition::After(chunk_type) => self.index_after(chunk_type)?,
+            ChunkPosition::Index(index) if *index > self.chunks.len() => {
+                return Err(PngError::InvalidChunkIndex(*index).into())
+            }
+            ChunkPosition::Index(index) => *index,
+            ChunkPosition::End => self.chunks.len(),
+        };
+
+        self.chunks.splice(index..index, chunks);
+        Ok(index)
+    }
+
+    fn index_after(&self, chunk_type: &str) -> Result<usize> {
+        match self.position_of(chunk_type) {
+            Some(pos) => Ok(pos + 1),
+            None => Err(PngError::InvalidChunkType.into()),
+        }
+    }
+
+    pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
+        if let Some(pos) = self.position_of(chunk_type) {
             let target = self.chunks.remove(pos);
             Ok(target)
         } else {
-            Err(Box::from(PngError::InvalidChunkType))
+            Err(PngError::InvalidChunkType.into())
         }
     }
 
+    pub fn remove_chunk_at(&mut self, index: usize) -> Result<Chunk> {
+        if index >= self.chunks.len() {
+            return Err(PngError::InvalidChunkIndex(index).into());
+        }
+
+        Ok(self.chunks.remove(index))
+    }
+
     pub fn header(&self) -> &[u8; 8] {
         &Self::STANDARD_HEADER
     }
@@ -42,20 +127,80 @@ impl Png {
         self.chunks.as_slice()
     }
 
+    pub fn position_of(&self, chunk_type: &str) -> Option<usize> {
+        self.chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+        self.chunks_by_type(chunk_type).next()
+    }
+
+    /// Returns every chunk of type `chunk_type`, in the order they appear in the file.
+    pub fn chunks_by_type<'a, 'b>(
+        &'a self,
+        chunk_type: &'b str,
+    ) -> impl Iterator<Item = &'a Chunk> + 'b
+    where
+        'a: 'b,
+    {
         self.chunks
             .iter()
-            .find(|&c| c.chunk_type().to_string() == chunk_type)
+            .filter(move |chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+
+    /// Parses and validates the `IHDR` chunk, which has to be the first chunk of the image.
+    pub fn ihdr(&self) -> Result<Ihdr> {
+        match self.chunks.first() {
+            Some(chunk) if chunk.chunk_type().to_string() == Self::IHDR => {
+                Ihdr::try_from(chunk.data())
+            }
+            _ if self.position_of(Self::IHDR).is_some() => Err(IhdrError::NotFirst.into()),
+            _ => Err(IhdrError::Missing.into()),
+        }
+    }
+
+    /// Bytes after the last chunk that do not form a chunk themselves, such as data appended
+    /// to the file by a camera or web service.
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing_data
+    }
+
+    pub fn set_trailing_data(&mut self, trailing_data: Vec<u8>) {
+        self.trailing_data = trailing_data;
+    }
+
+    /// Removes the trailing data and returns it.
+    pub fn strip_trailing_data(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.trailing_data)
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
-        let header = self.header().iter().copied().collect::<Vec<u8>>();
+        let header = self.header().to_vec();
         let rest: Vec<u8> = self
             .chunks
             .iter()
             .flat_map(|chunk| chunk.as_bytes().into_iter())
             .collect();
-        header.into_iter().chain(rest.into_iter()).collect()
+        header
+            .into_iter()
+            .chain(rest)
+            .chain(self.trailing_data.iter().copied())
+            .collect()
+    }
+
+    /// Parses what follows `IEND`. Chunks that parse cleanly are kept, since messages placed at
+    /// `ChunkPosition::End` live there, and everything from the first bytes that do not form a
+    /// chunk onwards is trailing data.
+    fn parse_after_iend(value: &[u8]) -> (Vec<Chunk>, &[u8]) {
+        let mut read_pos = 0;
+        let mut chunks = Vec::new();
+        while let Ok(chunk) = Chunk::try_from(&value[read_pos..]) {
+            read_pos += chunk.length() + Chunk::CHUNK_META_SIZE;
+            chunks.push(chunk);
+        }
+        (chunks, &value[read_pos..])
     }
 }
 
@@ -64,13 +209,61 @@ impl Display for Png {
         for chunk in self.chunks() {
             writeln!(f, "{}", chunk)?;
         }
+        if !self.trailing_data.is_empty() {
+            writeln!(f, "{}", TrailingData(&self.trailing_data))?;
+        }
         Ok(())
     }
 }
 
+/// Where a new chunk should be placed within a `Png`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChunkPosition {
+    BeforeIend,
+    AfterIhdr,
+    After(String),
+    Index(usize),
+    End,
+}
+
+impl FromStr for ChunkPosition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "before-iend" => return Ok(ChunkPosition::BeforeIend),
+            "after-ihdr" => return Ok(ChunkPosition::AfterIhdr),
+            "end" => return Ok(ChunkPosition::End),
+            _ => {}
+        }
+
+        if let Some(chunk_type) = s.strip_prefix("after:") {
+            if chunk_type.is_empty() || ChunkType::from_str(chunk_type).is_err() {
+                return Err(PngError::InvalidChunkPosition(s.to_string()).into());
+            }
+            return Ok(ChunkPosition::After(chunk_type.to_string()));
+        }
+
+        if let Some(index) = s.strip_prefix("index:") {
+            return index
+                .parse()
+                .map(ChunkPosition::Index)
+                .map_err(|_| PngError::InvalidChunkPosition(s.to_string()).into());
+        }
+
+        Err(PngError::InvalidChunkPosition(s.to_string()).into())
+    }
+}
+
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum PngError {
     InvalidChunkType,
+    InvalidChunkIndex(usize),
+    InvalidChunkPosition(String),
+    InvalidMessageIndex(usize),
+    InvalidStructure(usize),
+    TrailingDataPresent(usize),
     InvalidHeaderLength,
     InvalidHeader,
 }
@@ -81,6 +274,26 @@ impl Display for PngError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             PngError::InvalidChunkType => write!(f, "invalid chunk type"),
+            PngError::InvalidChunkIndex(index) => {
+                write!(f, "chunk index {} is out of bounds", index)
+            }
+            PngError::InvalidChunkPosition(position) => write!(
+                f,
+                "invalid chunk position '{}', expected one of before-iend, after-ihdr, \
+                 after:<type>, index:<n> or end",
+                position
+            ),
+            PngError::InvalidMessageIndex(index) => {
+                write!(f, "there is no message with index {}", index)
+            }
+            PngError::InvalidStructure(count) => {
+                write!(f, "image breaks {} rules of the PNG spec", count)
+            }
+            PngError::TrailingDataPresent(length) => write!(
+                f,
+                "image already has {} bytes of trailing data, strip them first",
+                length
+            ),
             PngError::InvalidHeaderLength => write!(
                 f,
                 "invalid header length, expected header of len {}",
@@ -93,47 +306,193 @@ impl Display for PngError {
     }
 }
 
-impl TryFrom<&[u8]> for Png {
-    type Error = Error;
+impl Png {
+    /// Parses `value` while recovering from damaged chunks instead of failing.
+    ///
+    /// Chunks with a bad CRC are kept, chunks with an unreadable type are skipped, parsing stops
+    /// at a truncated chunk, and bytes after `IEND` that are not chunks are kept as trailing
+    /// data. Each of these is reported as a `Diagnostic`. Only an invalid PNG signature is
+    /// treated as a hard error.
+    pub fn parse_lenient(value: &[u8]) -> Result<(Png, Vec<Diagnostic>)> {
+        let value = Self::strip_header(value)?;
 
-    fn try_from(value: &[u8]) -> Result<Self> {
+        let mut read_pos = 0;
+        let mut chunks = Vec::new();
+        let mut diagnostics = Vec::new();
+
+        while read_pos < value.len() {
+            let bytes = &value[read_pos..];
+            let offset = Self::STANDARD_HEADER.len() + read_pos;
+
+            let error = match Chunk::parse_unchecked(bytes) {
+                Ok((chunk, expected)) => {
+                    let actual = chunk.crc();
+                    if actual != expected {
+                        diagnostics.push(Diagnostic::Chunk {
+                            index: Some(chunks.len()),
+                            offset,
+                            error: ChunkParseError::BadCrc { expected, actual },
+                        });
+                    }
+
+                    read_pos += chunk.length() + Chunk::CHUNK_META_SIZE;
+
+                    let is_iend = chunk.chunk_type().to_string() == Self::IEND;
+                    chunks.push(chunk);
+                    if is_iend {
+                        break;
+                    }
+                    continue;
+                }
+                Err(Error::Chunk { source, .. }) => source,
+                Err(err) => return Err(err),
+            };
+
+            let skip = match error {
+                ChunkParseError::BadChunkType(_) => {
+                    let length = u32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize;
+                    Some(length + Chunk::CHUNK_META_SIZE).filter(|&skip| skip <= bytes.len())
+                }
+                _ => None,
+            };
+
+            diagnostics.push(Diagnostic::Chunk {
+                index: None,
+                offset,
+                error,
+            });
+
+            match skip {
+                Some(skip) => read_pos += skip,
+                None => {
+                    read_pos = value.len();
+                    break;
+                }
+            }
+        }
+
+        let (after_iend, trailing_data) = Self::parse_after_iend(&value[read_pos..]);
+        read_pos += after_iend
+            .iter()
+            .map(|chunk| chunk.length() + Chunk::CHUNK_META_SIZE)
+            .sum::<usize>();
+        chunks.extend(after_iend);
+
+        if !trailing_data.is_empty() {
+            diagnostics.push(Diagnostic::TrailingData {
+                offset: Self::STANDARD_HEADER.len() + read_pos,
+                length: trailing_data.len(),
+            });
+        }
+
+        let png = Png {
+            chunks,
+            trailing_data: trailing_data.to_vec(),
+        };
+        Ok((png, diagnostics))
+    }
+
+    pub(crate) fn strip_header(value: &[u8]) -> Result<&[u8]> {
         if value.len() < Self::STANDARD_HEADER.len() {
-            return Err(Box::from(PngError::InvalidHeaderLength));
+            return Err(PngError::InvalidHeaderLength.into());
         }
 
         let (header, value) = value.split_at(Self::STANDARD_HEADER.len());
 
         if header != Self::STANDARD_HEADER {
-            return Err(Box::from(PngError::InvalidHeader));
+            return Err(PngError::InvalidHeader.into());
         }
 
+        Ok(value)
+    }
+}
+
+impl TryFrom<&[u8]> for Png {
+    type Error = Error;
+
+    fn try_from(value: &[u8]) -> Result<Self> {
+        let value = Self::strip_header(value)?;
+
         let mut read_pos = 0;
         let mut chunks = Vec::new();
 
         while read_pos < value.len() {
             let bytes = &value[read_pos..];
-            let current_chunk = Chunk::try_from(bytes)?;
+            let current_chunk = Chunk::try_from(bytes).map_err(|err| {
+                err.at_chunk(chunks.len(), Self::STANDARD_HEADER.len() + read_pos)
+            })?;
             read_pos += current_chunk.length() + Chunk::CHUNK_META_SIZE;
 
+            let is_iend = current_chunk.chunk_type().to_string() == Self::IEND;
             chunks.push(current_chunk);
+            if is_iend {
+                let (after_iend, trailing_data) = Self::parse_after_iend(&value[read_pos..]);
+                chunks.extend(after_iend);
+                return Ok(Png {
+                    chunks,
+                    trailing_data: trailing_data.to_vec(),
+                });
+            }
         }
 
-        Ok(Png { chunks })
+        Ok(Png::from_chunks(chunks))
     }
 }
 
+/// A problem found while parsing a PNG with `Png::parse_lenient`. Offsets are byte offsets
+/// from the start of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    Chunk {
+        /// Index of the chunk in the parsed `Png`, or `None` if it was left out.
+        index: Option<usize>,
+        offset: usize,
+        error: ChunkParseError,
+    },
+    TrailingData {
+        offset: usize,
+        length: usize,
+    },
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Diagnostic::Chunk {
+                index: Some(index),
+                offset,
+                error,
+            } => write!(f, "chunk {} at byte {}: {}", index, offset, error),
+            Diagnostic::Chunk {
+                index: None,
+                offset,
+                error,
+            } => write!(f, "skipped chunk at byte {}: {}", offset, error),
+            Diagnostic::TrailingData { offset, length } => write!(
+                f,
+                "{} bytes of trailing data after IEND at byte {}",
+                length, offset
+            ),
+        }
+    }
+}
+
+/// `sample.png`, a 500x500 RGBA image, for the tests of any module that needs a real image.
+#[cfg(test)]
+pub(crate) fn sample() -> Png {
+    Png::try_from(&include_bytes!("../sample.png")[..]).unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -216,6 +575,182 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_bad_crc_reports_chunk_location() {
+        let mut bytes = PNG_FILE.to_vec();
+        // Corrupt the CRC of the sRGB chunk, the second chunk in the file.
+        bytes[45] ^= 0xFF;
+
+        match Png::try_from(bytes.as_ref()) {
+            Err(Error::Chunk {
+                index: Some(1),
+                offset: 33,
+                source: ChunkParseError::BadCrc { .. },
+            }) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_truncated_file() {
+        for len in [9, 20, 40, PNG_FILE.len() - 1] {
+            match Png::try_from(&PNG_FILE[..len]) {
+                Err(Error::Chunk {
+                    source: ChunkParseError::Truncated { .. },
+                    ..
+                }) => {}
+                other => panic!("unexpected result: {:?}", other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_lenient_valid_file() {
+        let (png, diagnostics) = Png::parse_lenient(&PNG_FILE[..]).unwrap();
+        assert!(diagnostics.is_empty());
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_lenient_keeps_bad_crc_chunk() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.insert_before_iend(chunk_from_strings("TeSt", "Message").unwrap());
+        let mut bytes = png.as_bytes();
+        // The CRC of the inserted chunk ends 12 bytes before the end of the file.
+        let crc_pos = bytes.len() - 13;
+        bytes[crc_pos] ^= 0xFF;
+
+        assert!(Png::try_from(bytes.as_ref()).is_err());
+
+        let (png, diagnostics) = Png::parse_lenient(bytes.as_ref()).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(matches!(
+            diagnostics[0],
+            Diagnostic::Chunk {
+                error: ChunkParseError::BadCrc { .. },
+                ..
+            }
+        ));
+        let chunk = png.chunk_by_type("TeSt").unwrap();
+        assert_eq!(&chunk.data_as_string().unwrap(), "Message");
+        let index = png.chunks().len() - 2;
+        assert!(matches!(diagnostics[0], Diagnostic::Chunk { index: Some(i), .. } if i == index));
+        assert_ne!(chunk.stored_crc(), Some(chunk.crc()));
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_round_trip_corpus() {
+        use crate::png_ref::PngRef;
+        use crate::stream::{ChunkReader, ChunkWriter};
+
+        let mut with_trailing_data = PNG_FILE.to_vec();
+        with_trailing_data.extend_from_slice(b"garbage");
+        let corpus: [&[u8]; 5] = [
+            &PNG_FILE,
+            include_bytes!("../sample.png"),
+            include_bytes!("../sample-out.png"),
+            include_bytes!("../sample-out-2.png"),
+            &with_trailing_data,
+        ];
+
+        for bytes in corpus {
+            assert_eq!(Png::try_from(bytes).unwrap().as_bytes(), bytes);
+            assert_eq!(Png::parse_lenient(bytes).unwrap().0.as_bytes(), bytes);
+            let png_ref = PngRef::try_from(bytes).unwrap();
+            assert_eq!(png_ref.to_png().unwrap().as_bytes(), bytes);
+
+            let mut reader = ChunkReader::new(bytes).unwrap();
+            let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+            for chunk in reader.by_ref() {
+                writer.write_chunk(&chunk.unwrap()).unwrap();
+            }
+            writer.write_trailing_data(reader.trailing_data()).unwrap();
+            assert_eq!(writer.into_inner().unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_lenient_skips_bad_chunk_type() {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend(chunk_from_strings("FrSt", "first").unwrap().as_bytes());
+        let mut bad_chunk = chunk_from_strings("BaDt", "bad").unwrap().as_bytes();
+        bad_chunk[4] = b'1';
+        bytes.extend(bad_chunk);
+        bytes.extend(chunk_from_strings("LASt", "last").unwrap().as_bytes());
+
+        let (png, diagnostics) = Png::parse_lenient(bytes.as_ref()).unwrap();
+        assert_eq!(png.chunks().len(), 2);
+        assert!(matches!(
+            diagnostics.as_slice(),
+            [Diagnostic::Chunk {
+                index: None,
+                offset: 25,
+                error: ChunkParseError::BadChunkType(_),
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_lenient_truncated_and_trailing() {
+        let (png, diagnostics) = Png::parse_lenient(&PNG_FILE[..PNG_FILE.len() - 1]).unwrap();
+        assert!(png.chunk_by_type("IDAT").is_some());
+        assert!(matches!(
+            diagnostics.as_slice(),
+            [Diagnostic::Chunk {
+                error: ChunkParseError::Truncated { .. },
+                ..
+            }]
+        ));
+
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"garbage");
+        let (png, diagnostics) = Png::parse_lenient(bytes.as_ref()).unwrap();
+        assert_eq!(png.trailing_data(), b"garbage");
+        assert_eq!(png.as_bytes(), bytes);
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::TrailingData {
+                offset: PNG_FILE.len(),
+                length: 7
+            }]
+        );
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"garbage");
+
+        let mut png = Png::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(png.trailing_data(), b"garbage");
+        assert_eq!(png.as_bytes(), bytes);
+
+        assert_eq!(png.strip_trailing_data(), b"garbage");
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_chunks_after_iend() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(chunk_from_strings("ruSt", "after IEND").unwrap());
+        png.set_trailing_data(b"garbage".to_vec());
+
+        let parsed = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert_eq!(parsed.chunks().last().unwrap().data(), b"after IEND");
+        assert_eq!(parsed.trailing_data(), b"garbage");
+    }
+
+    #[test]
+    fn test_invalid_header_error() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[0] = 13;
+        assert!(matches!(
+            Png::try_from(bytes.as_ref()),
+            Err(Error::Png(PngError::InvalidHeader))
+        ));
+    }
+
     #[test]
     fn test_list_chunks() {
         let png = testing_png();
@@ -231,6 +766,19 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
     }
 
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("FrSt", "Another first chunk").unwrap());
+
+        let data: Vec<&[u8]> = png.chunks_by_type("FrSt").map(Chunk::data).collect();
+        assert_eq!(
+            data,
+            vec![&b"I am the first chunk"[..], &b"Another first chunk"[..]]
+        );
+        assert_eq!(png.chunks_by_type("NoPe").count(), 0);
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -249,17 +797,133 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk_from_strings("TeSt", "Message").unwrap())
+            .unwrap();
+        assert_eq!(png.position_of("TeSt"), Some(1));
+        assert!(png
+            .insert_chunk(10, chunk_from_strings("TeSt", "Message").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_insert_before_iend() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.insert_before_iend(chunk_from_strings("TeSt", "Message").unwrap());
+        let chunks = png.chunks();
+        assert_eq!(chunks[chunks.len() - 1].chunk_type().to_string(), "IEND");
+        assert_eq!(chunks[chunks.len() - 2].chunk_type().to_string(), "TeSt");
+    }
+
+    #[test]
+    fn test_insert_before_iend_without_iend() {
+        let mut png = testing_png();
+        png.insert_before_iend(chunk_from_strings("TeSt", "Message").unwrap());
+        assert_eq!(png.position_of("TeSt"), Some(3));
+    }
+
+    #[test]
+    fn test_insert_after_ihdr() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.insert_after_ihdr(chunk_from_strings("TeSt", "Message").unwrap())
+            .unwrap();
+        assert_eq!(png.position_of("TeSt"), Some(1));
+    }
+
+    #[test]
+    fn test_insert_after() {
+        let mut png = testing_png();
+        png.insert_after("miDl", chunk_from_strings("TeSt", "Message").unwrap())
+            .unwrap();
+        assert_eq!(png.position_of("TeSt"), Some(2));
+        assert!(png
+            .insert_after("NoPe", chunk_from_strings("TeSt", "Message").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_place_chunks() {
+        let mut png = testing_png();
+        let chunks = vec![
+            chunk_from_strings("TeSt", "one").unwrap(),
+            chunk_from_strings("TeSt", "two").unwrap(),
+        ];
+        let index = png
+            .place_chunks(&ChunkPosition::After("FrSt".to_string()), chunks)
+            .unwrap();
+        assert_eq!(index, 1);
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["FrSt", "TeSt", "TeSt", "miDl", "LASt"]);
+        assert_eq!(png.chunks()[2].data(), b"two");
+
+        assert!(png.place_chunks(&ChunkPosition::Index(10), vec![]).is_err());
+        assert!(png.place_chunks(&ChunkPosition::AfterIhdr, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_chunk_position_from_str() {
+        assert_eq!(
+            ChunkPosition::from_str("before-iend").unwrap(),
+            ChunkPosition::BeforeIend
+        );
+        assert_eq!(
+            ChunkPosition::from_str("after-ihdr").unwrap(),
+            ChunkPosition::AfterIhdr
+        );
+        assert_eq!(
+            ChunkPosition::from_str("after:gAMA").unwrap(),
+            ChunkPosition::After(String::from("gAMA"))
+        );
+        assert_eq!(
+            ChunkPosition::from_str("index:3").unwrap(),
+            ChunkPosition::Index(3)
+        );
+        assert_eq!(ChunkPosition::from_str("end").unwrap(), ChunkPosition::End);
+        assert!(ChunkPosition::from_str("middle").is_err());
+        assert!(ChunkPosition::from_str("after:1234").is_err());
+        assert!(matches!(
+            ChunkPosition::from_str("after:"),
+            Err(Error::Png(PngError::InvalidChunkPosition(_)))
+        ));
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
         assert!(png.is_ok());
     }
 
+    #[test]
+    fn test_ihdr() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let ihdr = png.ihdr().unwrap();
+        assert_eq!(ihdr.to_string(), "50x50, 8-bit RGBA, not interlaced");
+
+        assert!(matches!(
+            testing_png().ihdr(),
+            Err(Error::Ihdr(IhdrError::Missing))
+        ));
+
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("IHDR").unwrap(),
+            ihdr.as_bytes(),
+        ));
+        assert!(matches!(png.ihdr(), Err(Error::Ihdr(IhdrError::NotFirst))));
+    }
+
     #[test]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
-        let expected: Vec<u8> = PNG_FILE.iter().copied().collect();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
         assert_eq!(actual, expected);
     }
 
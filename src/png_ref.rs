@@ -0,0 +1,228 @@
+use crate::chunk::Chunk;
+use crate::chunk_ref::ChunkRef;
+use crate::ihdr::{Ihdr, IhdrError};
+use crate::png::Png;
+use crate::{Error, Result};
+
+/// A PNG borrowed from its bytes. Chunks are parsed lazily as they are iterated, so looking up
+/// a single chunk only touches the bytes before it.
+pub struct PngRef<'a> {
+    chunk_bytes: &'a [u8],
+}
+
+impl<'a> PngRef<'a> {
+    pub fn chunks(&self) -> ChunkRefs<'a> {
+        ChunkRefs {
+            bytes: self.chunk_bytes,
+            read_pos: 0,
+            index: 0,
+            after_iend: false,
+            done: false,
+        }
+    }
+
+    /// Returns the bytes after the last chunk, see `Png::trailing_data`. This has to read every
+    /// chunk to find where they end.
+    pub fn trailing_data(&self) -> Result<&'a [u8]> {
+        let mut chunks = self.chunks();
+        for chunk in chunks.by_ref() {
+            chunk?;
+        }
+        Ok(&chunks.bytes[chunks.read_pos..])
+    }
+
+    /// Returns the first chunk of type `chunk_type`, stopping as soon as it is found.
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Result<Option<ChunkRef<'a>>> {
+        self.chunks_by_type(chunk_type).next().transpose()
+    }
+
+    /// Returns every chunk of type `chunk_type`, along with any error met along the way.
+    pub fn chunks_by_type<'b>(
+        &self,
+        chunk_type: &'b str,
+    ) -> impl Iterator<Item = Result<ChunkRef<'a>>> + 'b
+    where
+        'a: 'b,
+    {
+        self.chunks().filter(move |chunk| match chunk {
+            Ok(chunk) => chunk.chunk_type().to_string() == chunk_type,
+            Err(_) => true,
+        })
+    }
+
+    /// Borrowed version of `Png::ihdr`.
+    pub fn ihdr(&self) -> Result<Ihdr> {
+        match self.chunks().next().transpose()? {
+            Some(chunk) if chunk.chunk_type().to_string() == Png::IHDR => {
+                Ihdr::try_from(chunk.data())
+            }
+            _ if self.chunk_by_type(Png::IHDR)?.is_some() => Err(IhdrError::NotFirst.into()),
+            _ => Err(IhdrError::Missing.into()),
+        }
+    }
+
+    pub fn to_png(&self) -> Result<Png> {
+        let chunks = self
+            .chunks()
+            .map(|chunk| chunk.map(|chunk| chunk.to_chunk()))
+            .collect::<Result<Vec<Chunk>>>()?;
+        let mut png = Png::from_chunks(chunks);
+        png.set_trailing_data(self.trailing_data()?.to_vec());
+        Ok(png)
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for PngRef<'a> {
+    type Error = Error;
+
+    /// Checks the PNG signature. Chunks are validated as they are iterated.
+    fn try_from(value: &'a [u8]) -> Result<Self> {
+        let chunk_bytes = Png::strip_header(value)?;
+        Ok(PngRef { chunk_bytes })
+    }
+}
+
+/// Iterator over the chunks of a `PngRef`. Stops after the first error, or without an error
+/// at the first bytes after `IEND` that do not form a chunk.
+pub struct ChunkRefs<'a> {
+    bytes: &'a [u8],
+    read_pos: usize,
+    index: usize,
+    after_iend: bool,
+    done: bool,
+}
+
+impl<'a> Iterator for ChunkRefs<'a> {
+    type Item = Result<ChunkRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.read_pos >= self.bytes.len() {
+            return None;
+        }
+
+        match ChunkRef::try_from(&self.bytes[self.read_pos..]) {
+            Ok(chunk) => {
+                self.read_pos += chunk.length() + Chunk::CHUNK_META_SIZE;
+                self.index += 1;
+                self.after_iend |= chunk.chunk_type().to_string() == Png::IEND;
+                Some(Ok(chunk))
+            }
+            Err(_) if self.after_iend => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                let offset = Png::STANDARD_HEADER.len() + self.read_pos;
+                self.read_pos = self.bytes.len();
+                Some(Err(err.at_chunk(self.index, offset)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::ChunkParseError;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"Hello".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ];
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_png_ref_chunks() {
+        let bytes = testing_bytes();
+        let png = PngRef::try_from(bytes.as_slice()).unwrap();
+
+        let types: Vec<String> = png
+            .chunks()
+            .map(|chunk| chunk.unwrap().chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["IHDR", "RuSt", "IEND"]);
+        assert_eq!(png.to_png().unwrap().as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_png_ref_trailing_data() {
+        let mut bytes = testing_bytes();
+        let png = PngRef::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.trailing_data().unwrap(), b"");
+
+        bytes.extend_from_slice(b"garbage");
+        let png = PngRef::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.chunks().count(), 3);
+        assert_eq!(png.trailing_data().unwrap(), b"garbage");
+        assert_eq!(png.to_png().unwrap().as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_png_ref_ihdr() {
+        let bytes = testing_bytes();
+        let png = PngRef::try_from(bytes.as_slice()).unwrap();
+        assert!(matches!(
+            png.ihdr(),
+            Err(Error::Ihdr(IhdrError::BadDimensions { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_png_ref_chunk_by_type() {
+        let bytes = testing_bytes();
+        let png = PngRef::try_from(bytes.as_slice()).unwrap();
+
+        let chunk = png.chunk_by_type("RuSt").unwrap().unwrap();
+        assert_eq!(chunk.data_as_str().unwrap(), "Hello");
+        assert!(png.chunk_by_type("NoPe").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_png_ref_chunks_by_type() {
+        let mut png = Png::try_from(testing_bytes().as_slice()).unwrap();
+        png.insert_after_ihdr(Chunk::new(
+            ChunkType::from_str("RuSt").unwrap(),
+            b"Hi".to_vec(),
+        ))
+        .unwrap();
+        let bytes = png.as_bytes();
+        let png = PngRef::try_from(bytes.as_slice()).unwrap();
+
+        let data: Vec<&[u8]> = png
+            .chunks_by_type("RuSt")
+            .map(|chunk| chunk.unwrap().data())
+            .collect();
+        assert_eq!(data, vec![&b"Hi"[..], &b"Hello"[..]]);
+    }
+
+    #[test]
+    fn test_png_ref_stops_at_target() {
+        let mut bytes = testing_bytes();
+        // Damage IEND, which comes after the chunk being looked up.
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        let png = PngRef::try_from(bytes.as_slice()).unwrap();
+
+        assert!(png.chunk_by_type("RuSt").unwrap().is_some());
+        assert!(matches!(
+            png.chunk_by_type("NoPe"),
+            Err(Error::Chunk {
+                index: Some(2),
+                offset: 50,
+                source: ChunkParseError::BadCrc { .. },
+            })
+        ));
+    }
+
+    #[test]
+    fn test_png_ref_invalid_header() {
+        let mut bytes = testing_bytes();
+        bytes[0] = 0;
+        assert!(PngRef::try_from(bytes.as_slice()).is_err());
+    }
+}
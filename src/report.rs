@@ -0,0 +1,268 @@
+//! Machine-readable descriptions of chunks and messages, for the `--format json` and
+//! `--format ndjson` output of the binary.
+//!
+//! Field names are part of the output format and should only ever be added to.
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::Ihdr;
+use crate::ops::StoredMessage;
+use crate::png::Png;
+use crate::png_ref::PngRef;
+use crate::sealed::Content;
+use crate::{chunk_ref, Result};
+use serde::Serialize;
+
+/// A chunk as it appears in the file. `offset` is the byte offset of its length field.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChunkReport {
+    pub index: usize,
+    pub offset: usize,
+    #[serde(rename = "type")]
+    pub chunk_type: String,
+    pub critical: bool,
+    pub public: bool,
+    pub reserved_bit_valid: bool,
+    pub safe_to_copy: bool,
+    pub length: usize,
+    pub crc: u32,
+    /// The decoded `IHDR`, on the `IHDR` chunk only and only if it is valid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<Ihdr>,
+}
+
+impl ChunkReport {
+    pub fn new(index: usize, offset: usize, chunk_type: &ChunkType, data: &[u8]) -> ChunkReport {
+        ChunkReport {
+            index,
+            offset,
+            chunk_type: chunk_type.to_string(),
+            critical: chunk_type.is_critical(),
+            public: chunk_type.is_public(),
+            reserved_bit_valid: chunk_type.is_reserved_bit_valid(),
+            safe_to_copy: chunk_type.is_safe_to_copy(),
+            length: data.len(),
+            crc: chunk_ref::crc(chunk_type, data),
+            image: if chunk_type.to_string() == Png::IHDR {
+                Ihdr::try_from(data).ok()
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// What a message turned out to hold once opened.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PayloadReport {
+    Text {
+        text: String,
+    },
+    File {
+        filename: Option<String>,
+        mime_type: String,
+        size: usize,
+    },
+    /// Still encrypted, because no key was given for it.
+    Encrypted {
+        size: usize,
+    },
+    /// Neither text nor an embedded file.
+    Binary {
+        size: usize,
+    },
+}
+
+impl PayloadReport {
+    /// Describes what `ops::open_payload` found in a payload, or a payload that was left
+    /// sealed, given as `None`, when there was no key for it.
+    pub fn new(payload: &[u8], content: Option<&Content>) -> PayloadReport {
+        match content {
+            None => PayloadReport::Encrypted {
+                size: payload.len(),
+            },
+            Some(Content::File(envelope)) => PayloadReport::File {
+                filename: envelope.filename().map(str::to_string),
+                mime_type: envelope.mime_type().to_string(),
+                size: envelope.size(),
+            },
+            Some(Content::Message(message)) => match std::str::from_utf8(message) {
+                Ok(text) => PayloadReport::Text {
+                    text: text.to_string(),
+                },
+                Err(_) => PayloadReport::Binary {
+                    size: message.len(),
+                },
+            },
+        }
+    }
+}
+
+/// A message along with the chunk it starts in. `number` counts the messages stored under
+/// the same chunk type, starting at 0.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MessageReport {
+    pub number: usize,
+    pub chunk: ChunkReport,
+    pub payload: PayloadReport,
+}
+
+impl MessageReport {
+    /// Describes `message`, whose first chunk is found in `chunks` by its index. `content` is
+    /// what opening the message found, as for `PayloadReport::new`.
+    pub fn new(
+        number: usize,
+        message: &StoredMessage,
+        chunks: &[ChunkReport],
+        content: Option<&Content>,
+    ) -> MessageReport {
+        MessageReport {
+            number,
+            chunk: chunks[message.index].clone(),
+            payload: PayloadReport::new(&message.payload, content),
+        }
+    }
+}
+
+/// Describes every chunk of `png`.
+pub fn chunk_reports(png: &Png) -> Vec<ChunkReport> {
+    let mut offset = Png::STANDARD_HEADER.len();
+    png.chunks()
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let report = ChunkReport::new(index, offset, chunk.chunk_type(), chunk.data());
+            offset += chunk.length() + Chunk::CHUNK_META_SIZE;
+            report
+        })
+        .collect()
+}
+
+/// Borrowed version of `chunk_reports`.
+pub fn chunk_reports_ref(png: &PngRef) -> Result<Vec<ChunkReport>> {
+    let mut offset = Png::STANDARD_HEADER.len();
+    let mut reports = Vec::new();
+    for (index, chunk) in png.chunks().enumerate() {
+        let chunk = chunk?;
+        reports.push(ChunkReport::new(
+            index,
+            offset,
+            chunk.chunk_type(),
+            chunk.data(),
+        ));
+        offset += chunk.length() + Chunk::CHUNK_META_SIZE;
+    }
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::Envelope;
+    use crate::sealed::{self, Kind};
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"Hello".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ];
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_chunk_reports() {
+        let png = testing_png();
+        let reports = chunk_reports(&png);
+
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[1].offset, 8 + 13 + 12);
+        assert_eq!(reports[2].offset, 8 + 13 + 12 + 5 + 12);
+        assert_eq!(reports[1].crc, png.chunks()[1].crc());
+
+        let bytes = png.as_bytes();
+        let png_ref = PngRef::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(chunk_reports_ref(&png_ref).unwrap(), reports);
+    }
+
+    #[test]
+    fn test_chunk_report_json() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let report = ChunkReport::new(1, 33, &chunk_type, b"Hello");
+
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            format!(
+                "{{\"index\":1,\"offset\":33,\"type\":\"RuSt\",\"critical\":true,\
+                 \"public\":false,\"reserved_bit_valid\":true,\"safe_to_copy\":true,\
+                 \"length\":5,\"crc\":{}}}",
+                chunk_ref::crc(&chunk_type, b"Hello")
+            )
+        );
+    }
+
+    #[test]
+    fn test_ihdr_report() {
+        let ihdr = Ihdr::try_from(&[0, 0, 0, 2, 0, 0, 0, 3, 8, 2, 0, 0, 0][..]).unwrap();
+        let chunk_type = ChunkType::from_str("IHDR").unwrap();
+        let report = ChunkReport::new(0, 8, &chunk_type, &ihdr.as_bytes());
+        assert_eq!(report.image, Some(ihdr));
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(json.ends_with(
+            "\"image\":{\"width\":2,\"height\":3,\"bit_depth\":8,\"color_type\":\"rgb\",\
+             \"interlace\":\"none\"}}"
+        ));
+
+        let report = ChunkReport::new(0, 8, &chunk_type, &[0; 13]);
+        assert_eq!(report.image, None);
+    }
+
+    #[test]
+    fn test_payload_reports() {
+        let message = |data: &[u8]| Content::Message(data.to_vec());
+        assert_eq!(
+            PayloadReport::new(b"\x01Hello", Some(&message(b"Hello"))),
+            PayloadReport::Text {
+                text: "Hello".to_string()
+            }
+        );
+        assert_eq!(
+            PayloadReport::new(b"\x01\xFF\xFE", Some(&message(&[0xFF, 0xFE]))),
+            PayloadReport::Binary { size: 2 }
+        );
+        assert_eq!(
+            PayloadReport::new(&sealed::wrap(Kind::Encrypted, b"sealed"), None),
+            PayloadReport::Encrypted { size: 11 }
+        );
+
+        let envelope = Envelope::new(None, "text/plain".to_string(), b"Hello".to_vec());
+        let report = PayloadReport::new(b"", Some(&Content::File(envelope)));
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            "{\"kind\":\"file\",\"filename\":null,\"mime_type\":\"text/plain\",\"size\":5}"
+        );
+    }
+
+    #[test]
+    fn test_message_report() {
+        let png = testing_png();
+        let chunks = chunk_reports(&png);
+        let message = StoredMessage {
+            index: 1,
+            payload: b"\x01Hello".to_vec(),
+        };
+
+        let content = Content::Message(b"Hello".to_vec());
+        let report = MessageReport::new(0, &message, &chunks, Some(&content));
+        assert_eq!(report.chunk, chunks[1]);
+        assert_eq!(
+            report.payload,
+            PayloadReport::Text {
+                text: "Hello".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use crate::envelope::Envelope;
+use crate::Result;
+use std::fmt::{Display, Formatter};
+
+/// Marks a sealed payload. Payloads without it are messages stored as they are, the way they
+/// were before payloads were sealed.
+pub const MAGIC: [u8; 4] = *b"PMSE";
+
+/// Size of what `wrap` puts in front of a body: the magic and the id of its kind.
+pub const HEADER_SIZE: usize = MAGIC.len() + 1;
+
+/// What a sealed payload holds, recorded after its magic so that it never has to be guessed
+/// from the content. A change to the layout of any kind gets a new id rather than reusing the
+/// old one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// A message, stored as it was given.
+    Message,
+    /// A file, stored as an `Envelope`.
+    File,
+    /// Another sealed payload, compressed with `compression::compress`.
+    Compressed,
+    /// Another sealed payload, encrypted with `crypto::encrypt_with`.
+    Encrypted,
+}
+
+impl Kind {
+    pub fn id(self) -> u8 {
+        match self {
+            Kind::Message => 1,
+            Kind::File => 2,
+            Kind::Compressed => 3,
+            Kind::Encrypted => 4,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Kind> {
+        match id {
+            1 => Some(Kind::Message),
+            2 => Some(Kind::File),
+            3 => Some(Kind::Compressed),
+            4 => Some(Kind::Encrypted),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kind::Message => write!(f, "message"),
+            Kind::File => write!(f, "file"),
+            Kind::Compressed => write!(f, "compressed payload"),
+            Kind::Encrypted => write!(f, "encrypted payload"),
+        }
+    }
+}
+
+/// What a sealed payload holds once every layer around it has been removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Content {
+    Message(Vec<u8>),
+    File(Envelope),
+}
+
+/// Prefixes `body` with the magic and the id of `kind`.
+pub fn wrap(kind: Kind, body: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(body.len() + HEADER_SIZE);
+    data.extend_from_slice(&MAGIC);
+    data.push(kind.id());
+    data.extend_from_slice(body);
+    data
+}
+
+/// Splits a payload written by `wrap` into its kind and body.
+pub fn unwrap(data: &[u8]) -> Result<(Kind, &[u8])> {
+    if !is_sealed(data) {
+        return Err(SealedError::NotSealed.into());
+    }
+    let id = data[MAGIC.len()];
+    let kind = Kind::from_id(id).ok_or(SealedError::UnknownKind(id))?;
+    Ok((kind, &data[HEADER_SIZE..]))
+}
+
+/// Returns whether `data` was written by `wrap`. Anything else is a message stored as is.
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.len() >= HEADER_SIZE && data[..MAGIC.len()] == MAGIC
+}
+
+/// Returns the kind of a payload written by `wrap`, or `None` if it is not one.
+pub fn kind(data: &[u8]) -> Option<Kind> {
+    unwrap(data).ok().map(|(kind, _)| kind)
+}
+
+/// Returns whether `data` is a sealed payload that needs a key to open.
+pub fn is_encrypted(data: &[u8]) -> bool {
+    kind(data) == Some(Kind::Encrypted)
+}
+
+#[derive(Debug)]
+pub enum SealedError {
+    /// A layer that has to be sealed, such as what was encrypted, is not.
+    NotSealed,
+    UnknownKind(u8),
+    /// A layer was found where it cannot be, such as compression around encryption.
+    Misplaced(Kind),
+    /// The text of a layer other than a message was asked for without copying it.
+    Unborrowable(Kind),
+}
+
+impl std::error::Error for SealedError {}
+
+impl Display for SealedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SealedError::NotSealed => write!(f, "message is missing its kind"),
+            SealedError::UnknownKind(id) => write!(f, "unknown message kind {}", id),
+            SealedError::Misplaced(kind) => write!(f, "unexpected {} in message", kind),
+            SealedError::Unborrowable(kind) => {
+                write!(f, "a {} cannot be read without copying it", kind)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn test_wrap_and_unwrap() {
+        for kind in [Kind::Message, Kind::File, Kind::Compressed, Kind::Encrypted] {
+            let data = wrap(kind, b"PMSG looks encrypted but is not");
+            assert_eq!(self::kind(&data), Some(kind));
+            assert_eq!(
+                unwrap(&data).unwrap(),
+                (kind, &b"PMSG looks encrypted but is not"[..])
+            );
+        }
+        assert!(is_encrypted(&wrap(Kind::Encrypted, b"")));
+        assert!(!is_encrypted(&wrap(Kind::Message, b"")));
+    }
+
+    #[test]
+    fn test_unwrap_invalid() {
+        for data in [&b""[..], b"Hello", b"PMSE"] {
+            assert!(!is_sealed(data));
+            assert!(matches!(
+                unwrap(data),
+                Err(Error::Sealed(SealedError::NotSealed))
+            ));
+            assert_eq!(kind(data), None);
+        }
+        assert!(matches!(
+            unwrap(b"PMSE\x09Hello"),
+            Err(Error::Sealed(SealedError::UnknownKind(9)))
+        ));
+    }
+}
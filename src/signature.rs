@@ -0,0 +1,586 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::crypto;
+use crate::fragment;
+use crate::png::{Png, PngError};
+use crate::Result;
+use chacha20poly1305::aead::OsRng;
+use ed25519_dalek::{Signer, Verifier};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Type of the companion chunk that holds the signature for a message chunk. It is ancillary,
+/// private and unsafe to copy, since an image signature depends on the critical chunks.
+pub const SIGNATURE_CHUNK_TYPE: &str = "siGN";
+
+/// Version 1 signed the data of every chunk of the type, without lengths, and is no longer
+/// accepted.
+const VERSION: u8 = 2;
+const KEY_SIZE: usize = 32;
+const SIGNATURE_SIZE: usize = 64;
+
+const MESSAGE_CONTEXT: &[u8] = b"png-msg message signature\0";
+const IMAGE_CONTEXT: &[u8] = b"png-msg image signature\0";
+
+/// An Ed25519 key used to sign messages.
+pub struct SigningKey(ed25519_dalek::SigningKey);
+
+impl SigningKey {
+    pub fn generate() -> SigningKey {
+        SigningKey(ed25519_dalek::SigningKey::generate(&mut OsRng))
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey(self.0.verifying_key())
+    }
+
+    pub fn bytes(&self) -> [u8; KEY_SIZE] {
+        self.0.to_bytes()
+    }
+}
+
+impl FromStr for SigningKey {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = crypto::decode_key(s)?;
+        Ok(SigningKey(ed25519_dalek::SigningKey::from_bytes(&bytes)))
+    }
+}
+
+impl Display for SigningKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        crypto::write_hex(f, &self.bytes())
+    }
+}
+
+/// The Ed25519 public key matching a `SigningKey`, which identifies the signer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyingKey(ed25519_dalek::VerifyingKey);
+
+impl VerifyingKey {
+    pub fn bytes(&self) -> [u8; KEY_SIZE] {
+        self.0.to_bytes()
+    }
+
+    fn from_bytes(bytes: &[u8; KEY_SIZE]) -> Result<VerifyingKey> {
+        ed25519_dalek::VerifyingKey::from_bytes(bytes)
+            .map(VerifyingKey)
+            .map_err(|_| SignatureError::InvalidKey.into())
+    }
+}
+
+impl FromStr for VerifyingKey {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::from_bytes(&crypto::decode_key(s)?)
+    }
+}
+
+impl Display for VerifyingKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        crypto::write_hex(f, &self.bytes())
+    }
+}
+
+/// The contents of a signature chunk.
+///
+/// Serialized as `version | chunk type | signer key | name length | name | message signature |
+/// image signature flag | image signature`, where the image signature is only present when the
+/// flag is 1.
+pub struct Signature {
+    chunk_type: ChunkType,
+    signer: VerifyingKey,
+    signer_name: String,
+    message_signature: ed25519_dalek::Signature,
+    image_signature: Option<ed25519_dalek::Signature>,
+}
+
+impl Signature {
+    /// Signs the message whose first chunk is at `index` in `png`: that chunk and, if it was
+    /// split with `fragment::split`, the chunks holding the rest of its fragments, found with
+    /// `message_indices`. Other messages of the same type are not covered. The signature chunk
+    /// belongs right after the last of these chunks. When `cover_image` is set, a second
+    /// signature is made over every chunk in the image other than signature chunks.
+    pub fn sign(
+        png: &Png,
+        index: usize,
+        key: &SigningKey,
+        signer_name: &str,
+        cover_image: bool,
+    ) -> Result<Signature> {
+        if signer_name.len() > u8::MAX as usize {
+            return Err(SignatureError::NameTooLong(signer_name.len()).into());
+        }
+        if index >= png.chunks().len() {
+            return Err(PngError::InvalidChunkIndex(index).into());
+        }
+
+        let chunks = message_chunks(png, index);
+        let chunk = chunks[0];
+
+        let mut signature = Signature {
+            chunk_type: *chunk.chunk_type(),
+            signer: key.verifying_key(),
+            signer_name: signer_name.to_string(),
+            message_signature: ed25519_dalek::Signature::from_bytes(&[0; SIGNATURE_SIZE]),
+            image_signature: None,
+        };
+
+        signature.message_signature = key.0.sign(&signature.message_payload(&chunks));
+        if cover_image {
+            signature.image_signature = Some(key.0.sign(&signature.image_payload(png)));
+        }
+
+        Ok(signature)
+    }
+
+    /// Finds the first signature made for a message stored under `chunk_type` in `png`, along
+    /// with the index of the chunk it is stored in.
+    pub fn find(png: &Png, chunk_type: &str) -> Result<Option<(usize, Signature)>> {
+        for (index, chunk) in png.chunks().iter().enumerate() {
+            if chunk.chunk_type().to_string() != SIGNATURE_CHUNK_TYPE {
+                continue;
+            }
+
+            let signature = Signature::try_from(chunk.data())?;
+            if signature.chunk_type.to_string() == chunk_type {
+                return Ok(Some((index, signature)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Checks this signature, stored in the chunk at `index` of `png`, against the message it
+    /// follows: the one the closest chunk of the signed type before it belongs to.
+    pub fn verify(&self, png: &Png, index: usize) -> Verification {
+        let chunk_type = self.chunk_type.to_string();
+        let message = png.chunks()[..index.min(png.chunks().len())]
+            .iter()
+            .rposition(|chunk| chunk.chunk_type().to_string() == chunk_type);
+        let chunks = message.map_or_else(Vec::new, |message| message_chunks(png, message));
+        let message_valid = !chunks.is_empty()
+            && self
+                .signer
+                .0
+                .verify(&self.message_payload(&chunks), &self.message_signature)
+                .is_ok();
+
+        let image = match &self.image_signature {
+            None => ImageCoverage::NotCovered,
+            Some(signature) => match self.signer.0.verify(&self.image_payload(png), signature) {
+                Ok(()) => ImageCoverage::Intact,
+                Err(_) => ImageCoverage::Modified,
+            },
+        };
+
+        Verification {
+            signer: self.signer,
+            signer_name: self.signer_name.clone(),
+            message_valid,
+            image,
+        }
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    pub fn signer(&self) -> &VerifyingKey {
+        &self.signer
+    }
+
+    pub fn signer_name(&self) -> &str {
+        &self.signer_name
+    }
+
+    pub fn covers_image(&self) -> bool {
+        self.image_signature.is_some()
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let chunk_type = ChunkType::from_str(SIGNATURE_CHUNK_TYPE).unwrap();
+        Chunk::new(chunk_type, self.as_bytes())
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header();
+        bytes.extend_from_slice(&self.message_signature.to_bytes());
+        match &self.image_signature {
+            Some(signature) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&signature.to_bytes());
+            }
+            None => bytes.push(0),
+        }
+        bytes
+    }
+
+    /// The fields that every signature commits to.
+    fn header(&self) -> Vec<u8> {
+        let mut bytes = vec![VERSION];
+        bytes.extend_from_slice(&self.chunk_type.bytes());
+        bytes.extend_from_slice(&self.signer.bytes());
+        bytes.push(self.signer_name.len() as u8);
+        bytes.extend_from_slice(self.signer_name.as_bytes());
+        bytes
+    }
+
+    fn message_payload(&self, chunks: &[&Chunk]) -> Vec<u8> {
+        let mut payload = MESSAGE_CONTEXT.to_vec();
+        payload.extend(self.header());
+        for chunk in chunks {
+            payload.extend_from_slice(&(chunk.length() as u32).to_be_bytes());
+            payload.extend_from_slice(chunk.data());
+        }
+        payload
+    }
+
+    fn image_payload(&self, png: &Png) -> Vec<u8> {
+        let mut payload = IMAGE_CONTEXT.to_vec();
+        payload.extend(self.header());
+        for chunk in png.chunks() {
+            if chunk.chunk_type().to_string() == SIGNATURE_CHUNK_TYPE {
+                continue;
+            }
+            payload.extend_from_slice(&(chunk.length() as u32).to_be_bytes());
+            payload.extend_from_slice(&chunk.chunk_type().bytes());
+            payload.extend_from_slice(chunk.data());
+        }
+        payload
+    }
+}
+
+impl TryFrom<&[u8]> for Signature {
+    type Error = crate::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self> {
+        let mut reader = FieldReader { value, offset: 0 };
+
+        let version = reader.take(1)?[0];
+        if version != VERSION {
+            return Err(SignatureError::UnsupportedVersion(version).into());
+        }
+
+        let chunk_type: [u8; 4] = reader.take(4)?.try_into().unwrap();
+        let chunk_type = ChunkType::try_from(chunk_type)?;
+        let signer = VerifyingKey::from_bytes(reader.take(KEY_SIZE)?.try_into().unwrap())?;
+        let name_length = reader.take(1)?[0] as usize;
+        let signer_name = std::str::from_utf8(reader.take(name_length)?)?.to_string();
+        let message_signature = read_signature(&mut reader)?;
+        let image_signature = match reader.take(1)?[0] {
+            0 => None,
+            _ => Some(read_signature(&mut reader)?),
+        };
+
+        if reader.offset != value.len() {
+            return Err(SignatureError::Malformed.into());
+        }
+
+        Ok(Signature {
+            chunk_type,
+            signer,
+            signer_name,
+            message_signature,
+            image_signature,
+        })
+    }
+}
+
+/// Indices of the chunks of the message the chunk at `index` belongs to, in order: just that
+/// chunk, or every chunk of its type holding a fragment of the same payload. `index` has to be
+/// in bounds.
+pub fn message_indices(png: &Png, index: usize) -> Vec<usize> {
+    let chunks = png.chunks();
+    let anchor = &chunks[index];
+    chunks
+        .iter()
+        .enumerate()
+        .filter(|&(other, chunk)| {
+            other == index
+                || (chunk.chunk_type() == anchor.chunk_type()
+                    && fragment::same_payload(anchor.data(), chunk.data()))
+        })
+        .map(|(other, _)| other)
+        .collect()
+}
+
+fn message_chunks(png: &Png, index: usize) -> Vec<&Chunk> {
+    message_indices(png, index)
+        .into_iter()
+        .map(|index| &png.chunks()[index])
+        .collect()
+}
+
+struct FieldReader<'a> {
+    value: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> FieldReader<'a> {
+    fn take(&mut self, needed: usize) -> Result<&'a [u8]> {
+        if self.value.len() - self.offset < needed {
+            return Err(SignatureError::Malformed.into());
+        }
+
+        let bytes = &self.value[self.offset..self.offset + needed];
+        self.offset += needed;
+        Ok(bytes)
+    }
+}
+
+fn read_signature(reader: &mut FieldReader) -> Result<ed25519_dalek::Signature> {
+    let bytes: [u8; SIGNATURE_SIZE] = reader.take(SIGNATURE_SIZE)?.try_into().unwrap();
+    Ok(ed25519_dalek::Signature::from_bytes(&bytes))
+}
+
+/// Whether the rest of the image was covered by a signature, and if so whether it still
+/// matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageCoverage {
+    NotCovered,
+    Intact,
+    Modified,
+}
+
+/// The outcome of checking a `Signature`.
+#[derive(Debug)]
+pub struct Verification {
+    pub signer: VerifyingKey,
+    pub signer_name: String,
+    pub message_valid: bool,
+    pub image: ImageCoverage,
+}
+
+impl Verification {
+    /// Whether the message is authentic and nothing the signature covers has changed.
+    pub fn is_valid(&self) -> bool {
+        self.message_valid && self.image != ImageCoverage::Modified
+    }
+}
+
+impl Display for Verification {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.signer_name.is_empty() {
+            writeln!(f, "Signer: {}", self.signer)?;
+        } else {
+            writeln!(f, "Signer: {} ({})", self.signer_name, self.signer)?;
+        }
+
+        let message = if self.message_valid {
+            "valid"
+        } else {
+            "INVALID"
+        };
+        writeln!(f, "Message signature: {}", message)?;
+
+        let image = match self.image {
+            ImageCoverage::NotCovered => "not covered by the signature",
+            ImageCoverage::Intact => "covered by the signature and unchanged",
+            ImageCoverage::Modified => "covered by the signature but MODIFIED",
+        };
+        write!(f, "Other chunks: {}", image)
+    }
+}
+
+#[derive(Debug)]
+pub enum SignatureError {
+    /// The message is not kept in a chunk, so there is nothing for a signature to cover.
+    NotInChunk(&'static str),
+    MissingSignature(String),
+    NameTooLong(usize),
+    UnsupportedVersion(u8),
+    Malformed,
+    InvalidKey,
+    UntrustedSigner([u8; KEY_SIZE]),
+    Invalid,
+}
+
+impl std::error::Error for SignatureError {}
+
+impl Display for SignatureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureError::NotInChunk(location) => write!(
+                f,
+                "only messages in a chunk can be signed, not ones in {}",
+                location
+            ),
+            SignatureError::MissingSignature(chunk_type) => {
+                write!(f, "no signature found for the {} chunk", chunk_type)
+            }
+            SignatureError::NameTooLong(length) => write!(
+                f,
+                "signer name is {} bytes, at most {} are allowed",
+                length,
+                u8::MAX
+            ),
+            SignatureError::UnsupportedVersion(version) => {
+                write!(f, "unsupported signature version {}", version)
+            }
+            SignatureError::Malformed => write!(f, "malformed signature chunk"),
+            SignatureError::InvalidKey => write!(f, "invalid Ed25519 public key"),
+            SignatureError::UntrustedSigner(signer) => {
+                write!(f, "message was signed by a different key: ")?;
+                crypto::write_hex(f, signer)
+            }
+            SignatureError::Invalid => write!(f, "signature verification failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn testing_png() -> Png {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![1, 2, 3]),
+            Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"Hello".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ];
+        Png::from_chunks(chunks)
+    }
+
+    fn signed_png(key: &SigningKey, cover_image: bool) -> Png {
+        let mut png = testing_png();
+        let signature = Signature::sign(&png, 2, key, "alice", cover_image).unwrap();
+        png.insert_after("RuSt", signature.to_chunk()).unwrap();
+        png
+    }
+
+    fn verify(png: &Png) -> Verification {
+        let (index, signature) = Signature::find(png, "RuSt").unwrap().unwrap();
+        signature.verify(png, index)
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::generate();
+        let png = signed_png(&key, false);
+
+        let verification = verify(&png);
+        assert_eq!(verification.signer, key.verifying_key());
+        assert_eq!(verification.signer_name, "alice");
+        assert!(verification.message_valid);
+        assert_eq!(verification.image, ImageCoverage::NotCovered);
+        assert!(verification.is_valid());
+    }
+
+    #[test]
+    fn test_tampered_message() {
+        let key = SigningKey::generate();
+        let mut png = signed_png(&key, false);
+        png.remove_chunk("RuSt").unwrap();
+        png.insert_before_iend(Chunk::new(
+            ChunkType::from_str("RuSt").unwrap(),
+            b"Hellp".to_vec(),
+        ));
+
+        let verification = verify(&png);
+        assert!(!verification.message_valid);
+        assert!(!verification.is_valid());
+    }
+
+    #[test]
+    fn test_other_messages_of_the_type() {
+        let key = SigningKey::generate();
+        let mut png = signed_png(&key, false);
+        png.insert_before_iend(Chunk::new(
+            ChunkType::from_str("RuSt").unwrap(),
+            b"Another message".to_vec(),
+        ));
+
+        let verification = verify(&png);
+        assert!(verification.is_valid());
+    }
+
+    #[test]
+    fn test_fragments() {
+        let fragmented_png = |fragments: &[Vec<u8>]| {
+            let mut png = testing_png();
+            png.remove_chunk("RuSt").unwrap();
+            for fragment in fragments {
+                let chunk_type = ChunkType::from_str("RuSt").unwrap();
+                png.insert_before_iend(Chunk::new(chunk_type, fragment.clone()));
+            }
+            png
+        };
+        let key = SigningKey::generate();
+        let fragments = fragment::split(
+            b"Hello, fragmented world, in pieces",
+            fragment::HEADER_SIZE + 8,
+        )
+        .unwrap();
+
+        let png = fragmented_png(&fragments);
+        let signature = Signature::sign(&png, 2, &key, "", false).unwrap();
+        let end = png.chunks().len() - 1;
+        assert_eq!(message_indices(&png, 2), (2..end).collect::<Vec<_>>());
+        assert!(signature.verify(&png, end).is_valid());
+
+        // Moving a byte from one fragment to the next changes what is signed.
+        let mut moved = fragments;
+        let byte = moved[0].pop().unwrap();
+        moved[1].insert(fragment::HEADER_SIZE, byte);
+        let png = fragmented_png(&moved);
+        assert!(!signature.verify(&png, end).message_valid);
+    }
+
+    #[test]
+    fn test_image_coverage() {
+        let key = SigningKey::generate();
+        let mut png = signed_png(&key, true);
+
+        let verification = verify(&png);
+        assert_eq!(verification.image, ImageCoverage::Intact);
+        assert!(verification.is_valid());
+
+        png.remove_chunk("IDAT").unwrap();
+        let verification = verify(&png);
+        assert!(verification.message_valid);
+        assert_eq!(verification.image, ImageCoverage::Modified);
+        assert!(!verification.is_valid());
+    }
+
+    #[test]
+    fn test_signature_bytes_round_trip() {
+        let key = SigningKey::generate();
+        let png = testing_png();
+        let signature = Signature::sign(&png, 2, &key, "alice", true).unwrap();
+
+        let parsed = Signature::try_from(signature.as_bytes().as_slice()).unwrap();
+        assert_eq!(parsed.as_bytes(), signature.as_bytes());
+        assert!(parsed.covers_image());
+
+        let bytes = signature.as_bytes();
+        assert!(matches!(
+            Signature::try_from(&bytes[..bytes.len() - 1]),
+            Err(Error::Signature(SignatureError::Malformed))
+        ));
+    }
+
+    #[test]
+    fn test_sign_missing_chunk() {
+        let key = SigningKey::generate();
+        assert!(matches!(
+            Signature::sign(&testing_png(), 4, &key, "", false),
+            Err(Error::Png(PngError::InvalidChunkIndex(4)))
+        ));
+    }
+
+    #[test]
+    fn test_key_text_round_trip() {
+        let key = SigningKey::generate();
+        let parsed = SigningKey::from_str(&key.to_string()).unwrap();
+        assert_eq!(parsed.verifying_key(), key.verifying_key());
+        assert_eq!(
+            VerifyingKey::from_str(&key.verifying_key().to_string()).unwrap(),
+            key.verifying_key()
+        );
+    }
+}
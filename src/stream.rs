@@ -0,0 +1,323 @@
+use crate::chunk::{Chunk, ChunkParseError};
+use crate::chunk_type::ChunkType;
+use crate::png::{Png, PngError};
+use crate::{Error, Result};
+use std::io::{ErrorKind, Read, Write};
+
+/// Reads chunks one at a time from a PNG stream, so that large files never have to be held in
+/// memory all at once.
+pub struct ChunkReader<R: Read> {
+    reader: R,
+    index: usize,
+    offset: usize,
+    done: bool,
+    seen_iend: bool,
+    /// Everything after `IEND`, read in one go since it may turn out not to be chunks at all,
+    /// and how much of it has been parsed as chunks so far.
+    after_iend: Option<(Vec<u8>, usize)>,
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Reads and checks the PNG signature, leaving `reader` positioned at the first chunk.
+    pub fn new(mut reader: R) -> Result<ChunkReader<R>> {
+        let mut header = [0; Png::STANDARD_HEADER.len()];
+        let read = read_fully(&mut reader, &mut header)?;
+
+        if read < header.len() {
+            return Err(PngError::InvalidHeaderLength.into());
+        }
+        if header != Png::STANDARD_HEADER {
+            return Err(PngError::InvalidHeader.into());
+        }
+
+        Ok(ChunkReader {
+            reader,
+            index: 0,
+            offset: header.len(),
+            done: false,
+            seen_iend: false,
+            after_iend: None,
+        })
+    }
+
+    /// Returns the bytes after the last chunk once every chunk has been read, see
+    /// `Png::trailing_data`.
+    pub fn trailing_data(&self) -> &[u8] {
+        match &self.after_iend {
+            Some((rest, read_pos)) if self.done => &rest[*read_pos..],
+            _ => &[],
+        }
+    }
+
+    /// Parses the next chunk out of what followed `IEND`, reading all of it first if needed.
+    fn next_after_iend(&mut self) -> Result<Option<Chunk>> {
+        if self.after_iend.is_none() {
+            let mut rest = Vec::new();
+            self.reader.read_to_end(&mut rest)?;
+            self.after_iend = Some((rest, 0));
+        }
+
+        let (rest, read_pos) = self.after_iend.as_mut().unwrap();
+        match Chunk::try_from(&rest[*read_pos..]) {
+            Ok(chunk) => {
+                *read_pos += chunk.length() + Chunk::CHUNK_META_SIZE;
+                Ok(Some(chunk))
+            }
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the next chunk, or returns `None` if the stream ends cleanly between chunks.
+    fn read_chunk(&mut self) -> Result<Option<Chunk>> {
+        let mut prefix = [0; Chunk::CHUNK_LENGTH_FIELD_SIZE + Chunk::CHUNK_TYPE_FIELD_SIZE];
+        let read = read_fully(&mut self.reader, &mut prefix)?;
+
+        if read == 0 {
+            return Ok(None);
+        }
+        if read < prefix.len() {
+            return Err(truncated(0, prefix.len(), read));
+        }
+
+        let (length, chunk_type) = prefix.split_at(Chunk::CHUNK_LENGTH_FIELD_SIZE);
+        let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+        let chunk_type: [u8; Chunk::CHUNK_TYPE_FIELD_SIZE] = chunk_type.try_into().unwrap();
+        let chunk_type = ChunkType::try_from(chunk_type)
+            .ok()
+            .filter(ChunkType::is_valid)
+            .ok_or(ChunkParseError::BadChunkType(chunk_type))?;
+
+        // Grow the buffer as data arrives rather than trusting the declared length up front.
+        let mut data = Vec::new();
+        let read = (&mut self.reader)
+            .take(length as u64)
+            .read_to_end(&mut data)?;
+        if read < length {
+            return Err(truncated(prefix.len(), length, read));
+        }
+
+        let mut crc = [0; Chunk::CHUNK_CRC_FIELD_SIZE];
+        let read = read_fully(&mut self.reader, &mut crc)?;
+        if read < crc.len() {
+            return Err(truncated(prefix.len() + length, crc.len(), read));
+        }
+
+        let chunk = Chunk::new(chunk_type, data);
+        let expected = u32::from_be_bytes(crc);
+        let actual = chunk.crc();
+        if actual != expected {
+            return Err(ChunkParseError::BadCrc { expected, actual }.into());
+        }
+
+        Ok(Some(chunk))
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let chunk = if self.seen_iend {
+            self.next_after_iend()
+        } else {
+            self.read_chunk()
+        };
+
+        match chunk {
+            Ok(Some(chunk)) => {
+                self.index += 1;
+                self.offset += chunk.length() + Chunk::CHUNK_META_SIZE;
+                self.seen_iend |= chunk.chunk_type().to_string() == Png::IEND;
+                Some(Ok(chunk))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err.at_chunk(self.index, self.offset)))
+            }
+        }
+    }
+}
+
+/// Writes a PNG signature followed by chunks to a stream.
+pub struct ChunkWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    /// Writes the PNG signature to `writer`.
+    pub fn new(mut writer: W) -> Result<ChunkWriter<W>> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        Ok(ChunkWriter { writer })
+    }
+
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        let length = chunk.length() as u32;
+        self.writer.write_all(&length.to_be_bytes())?;
+        self.writer.write_all(&chunk.chunk_type().bytes())?;
+        self.writer.write_all(chunk.data())?;
+        let crc = chunk.stored_crc().unwrap_or_else(|| chunk.crc());
+        self.writer.write_all(&crc.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Writes bytes that follow the last chunk as they are.
+    pub fn write_trailing_data(&mut self, data: &[u8]) -> Result<()> {
+        self.writer.write_all(data)?;
+        Ok(())
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Fills as much of `buf` as the reader allows, returning how many bytes were read.
+fn read_fully<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(read)
+}
+
+fn truncated(offset: usize, needed: usize, available: usize) -> Error {
+    ChunkParseError::Truncated {
+        offset,
+        needed,
+        available,
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"Hello".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ];
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_read_chunks() {
+        let bytes = testing_png().as_bytes();
+        let reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        let chunks: Vec<Chunk> = reader.collect::<Result<_>>().unwrap();
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].data(), b"Hello");
+    }
+
+    #[test]
+    fn test_read_invalid_header() {
+        let mut bytes = testing_png().as_bytes();
+        bytes[0] = 0;
+        assert!(matches!(
+            ChunkReader::new(bytes.as_slice()),
+            Err(Error::Png(PngError::InvalidHeader))
+        ));
+        assert!(matches!(
+            ChunkReader::new(&bytes[..3]),
+            Err(Error::Png(PngError::InvalidHeaderLength))
+        ));
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        let bytes = testing_png().as_bytes();
+        let mut reader = ChunkReader::new(&bytes[..bytes.len() - 2]).unwrap();
+
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_ok());
+        match reader.next() {
+            Some(Err(Error::Chunk {
+                index: Some(2),
+                offset: 50,
+                source:
+                    ChunkParseError::Truncated {
+                        offset: 8,
+                        needed: 4,
+                        available: 2,
+                    },
+            })) => {}
+            other => panic!("unexpected result: {:?}", other.map(|r| r.map(|_| ()))),
+        }
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_read_bad_crc() {
+        let mut bytes = testing_png().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        let reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        let result: Result<Vec<Chunk>> = reader.collect();
+
+        assert!(matches!(
+            result,
+            Err(Error::Chunk {
+                index: Some(2),
+                source: ChunkParseError::BadCrc { .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_read_trailing_data() {
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"after IEND".to_vec(),
+        ));
+        png.set_trailing_data(b"garbage".to_vec());
+        let bytes = png.as_bytes();
+
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        let chunks: Vec<Chunk> = reader.by_ref().collect::<Result<_>>().unwrap();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(reader.trailing_data(), b"garbage");
+
+        let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+        for chunk in &chunks {
+            writer.write_chunk(chunk).unwrap();
+        }
+        writer.write_trailing_data(reader.trailing_data()).unwrap();
+        assert_eq!(writer.into_inner().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_write_chunks() {
+        let png = testing_png();
+        let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+        for chunk in png.chunks() {
+            writer.write_chunk(chunk).unwrap();
+        }
+
+        assert_eq!(writer.into_inner().unwrap(), png.as_bytes());
+    }
+}
@@ -0,0 +1,548 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::Result;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+pub const TEXT: &str = "tEXt";
+pub const COMPRESSED_TEXT: &str = "zTXt";
+pub const INTERNATIONAL_TEXT: &str = "iTXt";
+
+/// Keywords are limited to this many bytes by the spec.
+pub const MAX_KEYWORD_LENGTH: usize = 79;
+
+/// Compressed text is never inflated past this size, so a hostile chunk cannot make us
+/// allocate without bound.
+pub const MAX_TEXT_SIZE: usize = 16 * 1024 * 1024;
+
+const COMPRESSION_METHOD_DEFLATE: u8 = 0;
+
+/// How a `TextEntry` is stored, which decides its chunk type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextKind {
+    /// `tEXt`, Latin-1 text stored as is.
+    Latin1,
+    /// `zTXt`, Latin-1 text compressed with zlib.
+    Compressed,
+    /// `iTXt`, UTF-8 text with an optional language tag and a translation of the keyword.
+    International {
+        language_tag: String,
+        translated_keyword: String,
+        compressed: bool,
+    },
+}
+
+/// A keyword and its text, as stored in one of the standard textual chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEntry {
+    keyword: String,
+    text: String,
+    kind: TextKind,
+}
+
+impl TextEntry {
+    /// Creates a `tEXt` entry.
+    pub fn new(keyword: &str, text: &str) -> TextEntry {
+        TextEntry::with_kind(keyword, text, TextKind::Latin1)
+    }
+
+    /// Creates a `zTXt` entry.
+    pub fn compressed(keyword: &str, text: &str) -> TextEntry {
+        TextEntry::with_kind(keyword, text, TextKind::Compressed)
+    }
+
+    /// Creates an `iTXt` entry. Either of `language_tag` and `translated_keyword` may be empty.
+    pub fn international(
+        keyword: &str,
+        text: &str,
+        language_tag: &str,
+        translated_keyword: &str,
+        compressed: bool,
+    ) -> TextEntry {
+        let kind = TextKind::International {
+            language_tag: language_tag.to_string(),
+            translated_keyword: translated_keyword.to_string(),
+            compressed,
+        };
+        TextEntry::with_kind(keyword, text, kind)
+    }
+
+    fn with_kind(keyword: &str, text: &str, kind: TextKind) -> TextEntry {
+        TextEntry {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            kind,
+        }
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn kind(&self) -> &TextKind {
+        &self.kind
+    }
+
+    pub fn chunk_type(&self) -> ChunkType {
+        let chunk_type = match self.kind {
+            TextKind::Latin1 => TEXT,
+            TextKind::Compressed => COMPRESSED_TEXT,
+            TextKind::International { .. } => INTERNATIONAL_TEXT,
+        };
+        ChunkType::from_str(chunk_type).unwrap()
+    }
+
+    /// Builds the chunk for this entry, checking the keyword and, for `tEXt` and `zTXt`, that
+    /// the text can be written as Latin-1 without null bytes.
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let mut data = encode_keyword(&self.keyword)?;
+        data.push(0);
+
+        match &self.kind {
+            TextKind::Latin1 => data.extend(encode_text(&self.text)?),
+            TextKind::Compressed => {
+                data.push(COMPRESSION_METHOD_DEFLATE);
+                data.extend(deflate(&encode_text(&self.text)?)?);
+            }
+            TextKind::International {
+                language_tag,
+                translated_keyword,
+                compressed,
+            } => {
+                if !language_tag
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+                {
+                    return Err(TextError::BadLanguageTag(language_tag.clone()).into());
+                }
+                if translated_keyword.contains('\0') {
+                    return Err(TextError::ContainsNull("translated keyword").into());
+                }
+
+                data.push(*compressed as u8);
+                data.push(COMPRESSION_METHOD_DEFLATE);
+                data.extend_from_slice(language_tag.as_bytes());
+                data.push(0);
+                data.extend_from_slice(translated_keyword.as_bytes());
+                data.push(0);
+                if *compressed {
+                    data.extend(deflate(self.text.as_bytes())?);
+                } else {
+                    data.extend_from_slice(self.text.as_bytes());
+                }
+            }
+        }
+
+        Ok(Chunk::new(self.chunk_type(), data))
+    }
+
+    /// Parses the data of a chunk of type `chunk_type`, which has to be one of the three
+    /// textual chunk types.
+    pub fn parse(chunk_type: &ChunkType, data: &[u8]) -> Result<TextEntry> {
+        let (keyword, rest) = split_at_null(data).ok_or(TextError::Malformed)?;
+        let keyword = decode_latin1(keyword);
+        check_keyword(&keyword)?;
+
+        let rest = match chunk_type.to_string().as_str() {
+            TEXT => return Ok(TextEntry::new(&keyword, &decode_latin1(rest))),
+            COMPRESSED_TEXT => {
+                let (method, compressed) = rest.split_first().ok_or(TextError::Malformed)?;
+                check_compression_method(*method)?;
+                let text = decode_latin1(&inflate(compressed)?);
+                return Ok(TextEntry::compressed(&keyword, &text));
+            }
+            INTERNATIONAL_TEXT => rest,
+            _ => return Err(TextError::NotText.into()),
+        };
+
+        let (flag, method, rest) = match rest {
+            [flag, method, rest @ ..] => (*flag, *method, rest),
+            _ => return Err(TextError::Malformed.into()),
+        };
+        let (language_tag, rest) = split_at_null(rest).ok_or(TextError::Malformed)?;
+        let (translated_keyword, text) = split_at_null(rest).ok_or(TextError::Malformed)?;
+
+        let compressed = match flag {
+            0 => false,
+            1 => {
+                check_compression_method(method)?;
+                true
+            }
+            _ => return Err(TextError::Malformed.into()),
+        };
+        let text = if compressed {
+            String::from_utf8(inflate(text)?).map_err(|err| err.utf8_error())?
+        } else {
+            std::str::from_utf8(text)?.to_string()
+        };
+
+        Ok(TextEntry::international(
+            &keyword,
+            &text,
+            std::str::from_utf8(language_tag)?,
+            std::str::from_utf8(translated_keyword)?,
+            compressed,
+        ))
+    }
+
+    /// Parses every textual chunk in `png`, in the order they appear in the file. A chunk that
+    /// cannot be parsed gives an error in its place rather than hiding the others.
+    pub fn find_all(png: &Png) -> Vec<Result<TextEntry>> {
+        png.chunks()
+            .iter()
+            .filter(|chunk| is_text_chunk(chunk.chunk_type()))
+            .map(TextEntry::try_from)
+            .collect()
+    }
+}
+
+impl TryFrom<&Chunk> for TextEntry {
+    type Error = crate::Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        TextEntry::parse(chunk.chunk_type(), chunk.data())
+    }
+}
+
+impl Display for TextEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}", self.keyword, self.chunk_type())?;
+        if let TextKind::International {
+            language_tag,
+            translated_keyword,
+            ..
+        } = &self.kind
+        {
+            if !language_tag.is_empty() {
+                write!(f, ", {}", language_tag)?;
+            }
+            if !translated_keyword.is_empty() {
+                write!(f, ", '{}'", translated_keyword)?;
+            }
+        }
+        write!(f, "): {}", self.text)
+    }
+}
+
+/// Adds `entry` to `png` just before `IEND`.
+pub fn add(png: &mut Png, entry: &TextEntry) -> Result<()> {
+    png.insert_before_iend(entry.to_chunk()?);
+    Ok(())
+}
+
+/// Removes every text entry with `keyword` from `png` and returns them. Textual chunks that
+/// cannot be parsed are left alone.
+pub fn remove(png: &mut Png, keyword: &str) -> Result<Vec<TextEntry>> {
+    let mut removed = Vec::new();
+    let mut index = 0;
+    while index < png.chunks().len() {
+        let chunk = &png.chunks()[index];
+        let entry = Some(chunk)
+            .filter(|chunk| is_text_chunk(chunk.chunk_type()))
+            .and_then(|chunk| TextEntry::try_from(chunk).ok());
+
+        match entry {
+            Some(entry) if entry.keyword == keyword => {
+                png.remove_chunk_at(index)?;
+                removed.push(entry);
+            }
+            _ => index += 1,
+        }
+    }
+    Ok(removed)
+}
+
+/// Returns whether chunks of `chunk_type` hold a `TextEntry`.
+pub fn is_text_chunk(chunk_type: &ChunkType) -> bool {
+    matches!(
+        chunk_type.to_string().as_str(),
+        TEXT | COMPRESSED_TEXT | INTERNATIONAL_TEXT
+    )
+}
+
+/// Checks the rules the spec sets for keywords: 1 to 79 printable Latin-1 characters, with
+/// no leading, trailing or consecutive spaces.
+fn check_keyword(keyword: &str) -> Result<()> {
+    let printable = |c: char| matches!(c as u32, 32..=126 | 161..=255);
+    let valid = !keyword.is_empty()
+        && keyword.chars().count() <= MAX_KEYWORD_LENGTH
+        && keyword.chars().all(printable)
+        && !keyword.starts_with(' ')
+        && !keyword.ends_with(' ')
+        && !keyword.contains("  ");
+
+    if !valid {
+        return Err(TextError::BadKeyword(keyword.to_string()).into());
+    }
+    Ok(())
+}
+
+fn check_compression_method(method: u8) -> Result<()> {
+    match method {
+        COMPRESSION_METHOD_DEFLATE => Ok(()),
+        other => Err(TextError::UnsupportedCompressionMethod(other).into()),
+    }
+}
+
+fn encode_keyword(keyword: &str) -> Result<Vec<u8>> {
+    check_keyword(keyword)?;
+    encode_latin1(keyword)
+}
+
+/// Encodes the text of a `tEXt` or `zTXt` entry, where a null byte would end it early.
+fn encode_text(text: &str) -> Result<Vec<u8>> {
+    if text.contains('\0') {
+        return Err(TextError::ContainsNull("text").into());
+    }
+    encode_latin1(text)
+}
+
+fn encode_latin1(text: &str) -> Result<Vec<u8>> {
+    text.chars()
+        .map(|c| u8::try_from(c).map_err(|_| TextError::NotLatin1(c).into()))
+        .collect()
+}
+
+fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+fn split_at_null(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let pos = data.iter().position(|&b| b == 0)?;
+    Some((&data[..pos], &data[pos + 1..]))
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn inflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut text = Vec::new();
+    ZlibDecoder::new(data)
+        .take(MAX_TEXT_SIZE as u64 + 1)
+        .read_to_end(&mut text)
+        .map_err(|_| TextError::Corrupt)?;
+
+    if text.len() > MAX_TEXT_SIZE {
+        return Err(TextError::TooLarge.into());
+    }
+    Ok(text)
+}
+
+#[derive(Debug)]
+pub enum TextError {
+    NotText,
+    BadKeyword(String),
+    BadLanguageTag(String),
+    NotLatin1(char),
+    /// The named field contains a null byte, which the chunk uses as a separator.
+    ContainsNull(&'static str),
+    UnsupportedCompressionMethod(u8),
+    Malformed,
+    Corrupt,
+    TooLarge,
+}
+
+impl std::error::Error for TextError {}
+
+impl Display for TextError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextError::NotText => write!(f, "chunk is not a tEXt, zTXt or iTXt chunk"),
+            TextError::BadKeyword(keyword) => write!(
+                f,
+                "invalid keyword '{}', keywords are 1 to {} printable Latin-1 characters \
+                 without leading, trailing or consecutive spaces",
+                keyword, MAX_KEYWORD_LENGTH
+            ),
+            TextError::BadLanguageTag(tag) => write!(f, "invalid language tag '{}'", tag),
+            TextError::NotLatin1(c) => write!(
+                f,
+                "'{}' cannot be stored as Latin-1, use an international text entry instead",
+                c
+            ),
+            TextError::ContainsNull(field) => write!(f, "the {} cannot contain a null byte", field),
+            TextError::UnsupportedCompressionMethod(method) => {
+                write!(f, "unsupported text compression method {}", method)
+            }
+            TextError::Malformed => write!(f, "text chunk is malformed"),
+            TextError::Corrupt => write!(f, "compressed text is corrupt"),
+            TextError::TooLarge => write!(
+                f,
+                "compressed text is larger than {} bytes once decompressed",
+                MAX_TEXT_SIZE
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn testing_png() -> Png {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ];
+        Png::from_chunks(chunks)
+    }
+
+    fn round_trip(entry: &TextEntry) -> TextEntry {
+        TextEntry::try_from(&entry.to_chunk().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_text() {
+        let entry = TextEntry::new("Author", "Zoë");
+        let chunk = entry.to_chunk().unwrap();
+
+        assert_eq!(chunk.chunk_type().to_string(), "tEXt");
+        assert_eq!(chunk.data(), b"Author\0Zo\xEB");
+        assert_eq!(round_trip(&entry), entry);
+    }
+
+    #[test]
+    fn test_compressed_text() {
+        let text = "Lorem ipsum dolor sit amet. ".repeat(20);
+        let entry = TextEntry::compressed("Description", &text);
+        let chunk = entry.to_chunk().unwrap();
+
+        assert_eq!(chunk.chunk_type().to_string(), "zTXt");
+        assert!(chunk.data().len() < text.len());
+        assert_eq!(round_trip(&entry), entry);
+    }
+
+    #[test]
+    fn test_international_text() {
+        for compressed in [false, true] {
+            let entry = TextEntry::international("Title", "東京の夜", "ja", "タイトル", compressed);
+            assert_eq!(entry.chunk_type().to_string(), "iTXt");
+            assert_eq!(round_trip(&entry), entry);
+        }
+
+        let entry = TextEntry::international("Title", "Night", "", "", false);
+        assert_eq!(entry.to_chunk().unwrap().data(), b"Title\0\0\0\0\0Night");
+    }
+
+    #[test]
+    fn test_not_latin1() {
+        assert!(matches!(
+            TextEntry::new("Title", "東京").to_chunk(),
+            Err(Error::Text(TextError::NotLatin1('東')))
+        ));
+    }
+
+    #[test]
+    fn test_bad_keywords() {
+        let long = "k".repeat(MAX_KEYWORD_LENGTH + 1);
+        for keyword in ["", " Title", "Title ", "Two  spaces", "Tab\t", &long] {
+            assert!(matches!(
+                TextEntry::new(keyword, "text").to_chunk(),
+                Err(Error::Text(TextError::BadKeyword(_)))
+            ));
+        }
+        assert!(TextEntry::new(&long[1..], "text").to_chunk().is_ok());
+    }
+
+    #[test]
+    fn test_malformed_chunks() {
+        let text = ChunkType::from_str("tEXt").unwrap();
+        let international = ChunkType::from_str("iTXt").unwrap();
+        let other = ChunkType::from_str("RuSt").unwrap();
+
+        assert!(matches!(
+            TextEntry::parse(&text, b"no separator"),
+            Err(Error::Text(TextError::Malformed))
+        ));
+        assert!(matches!(
+            TextEntry::parse(&international, b"Title\0\x01"),
+            Err(Error::Text(TextError::Malformed))
+        ));
+        assert!(matches!(
+            TextEntry::parse(&international, b"Title\0\x01\x01\0\0text"),
+            Err(Error::Text(TextError::UnsupportedCompressionMethod(1)))
+        ));
+        assert!(matches!(
+            TextEntry::parse(&other, b"Title\0text"),
+            Err(Error::Text(TextError::NotText))
+        ));
+    }
+
+    #[test]
+    fn test_add_find_and_remove() {
+        let mut png = testing_png();
+        add(&mut png, &TextEntry::new("Author", "Ferris")).unwrap();
+        add(&mut png, &TextEntry::compressed("Comment", "Hello")).unwrap();
+        add(&mut png, &TextEntry::new("Author", "Crab")).unwrap();
+
+        let entries = TextEntry::find_all(&png);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1].as_ref().unwrap().text(), "Hello");
+        assert_eq!(
+            png.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+
+        let removed = remove(&mut png, "Author").unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(removed[1].text(), "Crab");
+        assert_eq!(TextEntry::find_all(&png).len(), 1);
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_find_all_with_malformed_chunk() {
+        let mut png = testing_png();
+        add(&mut png, &TextEntry::new("Author", "Ferris")).unwrap();
+        png.insert_before_iend(Chunk::new(
+            ChunkType::from_str("tEXt").unwrap(),
+            b"no separator".to_vec(),
+        ));
+        add(&mut png, &TextEntry::new("Comment", "Hello")).unwrap();
+
+        let entries = TextEntry::find_all(&png);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].as_ref().unwrap().keyword(), "Author");
+        assert!(matches!(entries[1], Err(Error::Text(TextError::Malformed))));
+        assert_eq!(entries[2].as_ref().unwrap().keyword(), "Comment");
+    }
+
+    #[test]
+    fn test_null_bytes() {
+        for entry in [
+            TextEntry::new("Title", "Hello\0World"),
+            TextEntry::compressed("Title", "Hello\0World"),
+        ] {
+            assert!(matches!(
+                entry.to_chunk(),
+                Err(Error::Text(TextError::ContainsNull("text")))
+            ));
+        }
+        assert!(matches!(
+            TextEntry::international("Title", "Night", "en", "Ti\0tle", false).to_chunk(),
+            Err(Error::Text(TextError::ContainsNull("translated keyword")))
+        ));
+    }
+
+    #[test]
+    fn test_display() {
+        let entry = TextEntry::international("Title", "Nacht", "de", "Titel", false);
+        assert_eq!(entry.to_string(), "Title (iTXt, de, 'Titel'): Nacht");
+        assert_eq!(
+            TextEntry::new("Author", "Ferris").to_string(),
+            "Author (tEXt): Ferris"
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use crate::chunk_type::ChunkType;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Marks trailing data produced by `wrap`.
+pub const MAGIC: [u8; 4] = *b"PMTR";
+
+/// Size of the header in front of the payload: magic and the chunk type it is stored under.
+pub const HEADER_SIZE: usize = MAGIC.len() + 4;
+
+/// Wraps `payload` for storing after `IEND`. The chunk type is recorded so that the message can
+/// be looked up by type like one stored in a chunk.
+pub fn wrap(chunk_type: &ChunkType, payload: &[u8]) -> Vec<u8> {
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&chunk_type.bytes());
+    data.extend_from_slice(payload);
+    data
+}
+
+/// Returns the payload in `trailing_data` if it was wrapped by `wrap` under `chunk_type`.
+pub fn unwrap<'a>(trailing_data: &'a [u8], chunk_type: &str) -> Option<&'a [u8]> {
+    match self::chunk_type(trailing_data) {
+        Some(stored) if stored.to_string() == chunk_type => Some(&trailing_data[HEADER_SIZE..]),
+        _ => None,
+    }
+}
+
+/// Returns the chunk type a payload in `trailing_data` is stored under, or `None` if the
+/// trailing data was not written by `wrap`.
+pub fn chunk_type(trailing_data: &[u8]) -> Option<ChunkType> {
+    if !is_trailer(trailing_data) {
+        return None;
+    }
+    let chunk_type = std::str::from_utf8(&trailing_data[MAGIC.len()..HEADER_SIZE]).ok()?;
+    ChunkType::from_str(chunk_type).ok()
+}
+
+/// Returns whether `trailing_data` starts with the header written by `wrap`.
+pub fn is_trailer(trailing_data: &[u8]) -> bool {
+    trailing_data.len() >= HEADER_SIZE && trailing_data[..MAGIC.len()] == MAGIC
+}
+
+/// Describes trailing data for `print`, noting when it holds a message.
+pub struct TrailingData<'a>(pub &'a [u8]);
+
+impl Display for TrailingData<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Trailing data: {} bytes after IEND", self.0.len())?;
+        if let Some(chunk_type) = chunk_type(self.0) {
+            write!(f, ", holding a message stored under {}", chunk_type)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_and_unwrap() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let data = wrap(&chunk_type, b"Hello");
+
+        assert!(is_trailer(&data));
+        assert_eq!(self::chunk_type(&data), Some(chunk_type));
+        assert_eq!(unwrap(&data, "ruSt"), Some(&b"Hello"[..]));
+        assert_eq!(unwrap(&data, "ruSx"), None);
+        assert_eq!(
+            TrailingData(&data).to_string(),
+            "Trailing data: 13 bytes after IEND, holding a message stored under ruSt"
+        );
+    }
+
+    #[test]
+    fn test_foreign_trailing_data() {
+        assert!(!is_trailer(b"PMTR"));
+        assert_eq!(chunk_type(b"camera maker notes"), None);
+        assert_eq!(unwrap(b"PMTR1234Hello", "ruSt"), None);
+    }
+}
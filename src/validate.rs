@@ -0,0 +1,359 @@
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, Ihdr, IhdrError};
+use crate::png::Png;
+use crate::Error;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+const PLTE: &str = "PLTE";
+const IDAT: &str = "IDAT";
+
+/// Chunks that may appear at most once.
+const UNIQUE: &[&str] = &[
+    Png::IHDR,
+    PLTE,
+    Png::IEND,
+    "cHRM",
+    "gAMA",
+    "iCCP",
+    "sBIT",
+    "sRGB",
+    "bKGD",
+    "hIST",
+    "tRNS",
+    "pHYs",
+    "tIME",
+    "eXIf",
+];
+
+/// Chunks that describe the colors of the image and so have to come before `PLTE`.
+const BEFORE_PLTE: &[&str] = &["cHRM", "gAMA", "iCCP", "sBIT", "sRGB"];
+
+/// Chunks that refer to the palette and so have to come after `PLTE`.
+const AFTER_PLTE: &[&str] = &["bKGD", "hIST", "tRNS"];
+
+/// Chunks that have to come before the image data.
+const BEFORE_IDAT: &[&str] = &[
+    PLTE, "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "bKGD", "hIST", "tRNS", "pHYs", "sPLT",
+];
+
+/// Where the spec requires a chunk to be relative to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    BeforePlte,
+    AfterPlte,
+    BeforeIdat,
+}
+
+impl Display for Placement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Placement::BeforePlte => write!(f, "before PLTE"),
+            Placement::AfterPlte => write!(f, "after PLTE"),
+            Placement::BeforeIdat => write!(f, "before the first IDAT"),
+        }
+    }
+}
+
+/// A rule of the PNG spec on the order or number of chunks that an image breaks. `index` is
+/// the position of the offending chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    Missing(&'static str),
+    InvalidIhdr(IhdrError),
+    IhdrNotFirst {
+        index: usize,
+    },
+    IendNotLast {
+        index: usize,
+    },
+    Duplicate {
+        index: usize,
+        chunk_type: ChunkType,
+    },
+    Misplaced {
+        index: usize,
+        chunk_type: ChunkType,
+        placement: Placement,
+    },
+    IdatNotConsecutive {
+        index: usize,
+    },
+    UnexpectedPlte {
+        index: usize,
+        color_type: ColorType,
+    },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::Missing(chunk_type) => write!(f, "image has no {} chunk", chunk_type),
+            Violation::InvalidIhdr(err) => write!(f, "chunk 0: {}", err),
+            Violation::IhdrNotFirst { index } => {
+                write!(f, "chunk {}: IHDR has to be the first chunk", index)
+            }
+            Violation::IendNotLast { index } => {
+                write!(f, "chunk {}: IEND has to be the last chunk", index)
+            }
+            Violation::Duplicate { index, chunk_type } => {
+                write!(f, "chunk {}: {} may only appear once", index, chunk_type)
+            }
+            Violation::Misplaced {
+                index,
+                chunk_type,
+                placement,
+            } => write!(
+                f,
+                "chunk {}: {} has to come {}",
+                index, chunk_type, placement
+            ),
+            Violation::IdatNotConsecutive { index } => {
+                write!(f, "chunk {}: IDAT chunks have to be consecutive", index)
+            }
+            Violation::UnexpectedPlte { index, color_type } => write!(
+                f,
+                "chunk {}: PLTE is not allowed in {} images",
+                index, color_type
+            ),
+        }
+    }
+}
+
+impl Png {
+    /// Checks the chunks against the ordering and cardinality rules of the PNG spec, returning
+    /// every rule that is broken. An empty list means the structure of the image is valid.
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        let types: Vec<String> = self
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        let first = |wanted: &str| types.iter().position(|t| t == wanted);
+        let plte = first(PLTE);
+        let idat = first(IDAT);
+        let iend = first(Png::IEND);
+
+        let ihdr = match self.ihdr() {
+            Ok(ihdr) => Some(ihdr),
+            Err(Error::Ihdr(err)) => {
+                violations.push(match err {
+                    IhdrError::Missing => Violation::Missing(Png::IHDR),
+                    IhdrError::NotFirst => Violation::IhdrNotFirst {
+                        index: first(Png::IHDR).unwrap_or(0),
+                    },
+                    err => Violation::InvalidIhdr(err),
+                });
+                None
+            }
+            Err(_) => None,
+        };
+
+        let mut seen = HashSet::new();
+        for (index, (chunk, chunk_type)) in self.chunks().iter().zip(&types).enumerate() {
+            let chunk_type_name = chunk_type.as_str();
+            let chunk_type = *chunk.chunk_type();
+
+            if UNIQUE.contains(&chunk_type_name) && !seen.insert(chunk_type_name) {
+                violations.push(Violation::Duplicate { index, chunk_type });
+                continue;
+            }
+
+            let misplaced = |placement| Violation::Misplaced {
+                index,
+                chunk_type,
+                placement,
+            };
+            if BEFORE_PLTE.contains(&chunk_type_name) && plte.is_some_and(|plte| plte < index) {
+                violations.push(misplaced(Placement::BeforePlte));
+            }
+            if AFTER_PLTE.contains(&chunk_type_name) && plte.is_some_and(|plte| plte > index) {
+                violations.push(misplaced(Placement::AfterPlte));
+            }
+            if BEFORE_IDAT.contains(&chunk_type_name) && idat.is_some_and(|idat| idat < index) {
+                violations.push(misplaced(Placement::BeforeIdat));
+            }
+
+            if chunk_type_name == IDAT && idat != Some(index) && types[index - 1] != IDAT {
+                violations.push(Violation::IdatNotConsecutive { index });
+            }
+        }
+
+        if idat.is_none() {
+            violations.push(Violation::Missing(IDAT));
+        }
+
+        match iend {
+            Some(iend) if iend + 1 < types.len() => {
+                violations.push(Violation::IendNotLast { index: iend })
+            }
+            Some(_) => {}
+            None => violations.push(Violation::Missing(Png::IEND)),
+        }
+
+        if let Some(ihdr) = ihdr {
+            violations.extend(palette_violation(&ihdr, plte));
+        }
+
+        violations
+    }
+}
+
+/// Checks that `PLTE` is present when the color type needs it and absent when it forbids it.
+fn palette_violation(ihdr: &Ihdr, plte: Option<usize>) -> Option<Violation> {
+    match (ihdr.color_type, plte) {
+        (ColorType::Indexed, None) => Some(Violation::Missing(PLTE)),
+        (color_type @ (ColorType::Grayscale | ColorType::GrayscaleAlpha), Some(index)) => {
+            Some(Violation::UnexpectedPlte { index, color_type })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::ihdr::Interlace;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str) -> Chunk {
+        let data = match chunk_type {
+            "IHDR" => Ihdr {
+                width: 1,
+                height: 1,
+                bit_depth: 8,
+                color_type: ColorType::Indexed,
+                interlace: Interlace::None,
+            }
+            .as_bytes(),
+            _ => vec![],
+        };
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn png(chunk_types: &[&str]) -> Png {
+        Png::from_chunks(chunk_types.iter().map(|t| chunk(t)).collect())
+    }
+
+    #[test]
+    fn test_valid_structure() {
+        let png = png(&[
+            "IHDR", "gAMA", "PLTE", "tRNS", "IDAT", "IDAT", "tEXt", "IEND",
+        ]);
+        assert_eq!(png.validate(), vec![]);
+    }
+
+    #[test]
+    fn test_empty_png() {
+        assert_eq!(
+            png(&[]).validate(),
+            vec![
+                Violation::Missing("IHDR"),
+                Violation::Missing("IDAT"),
+                Violation::Missing("IEND"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ordering() {
+        let png = png(&[
+            "tEXt", "IHDR", "PLTE", "gAMA", "IDAT", "tEXt", "IDAT", "IEND", "tEXt",
+        ]);
+        let gama = ChunkType::from_str("gAMA").unwrap();
+
+        assert_eq!(
+            png.validate(),
+            vec![
+                Violation::IhdrNotFirst { index: 1 },
+                Violation::Misplaced {
+                    index: 3,
+                    chunk_type: gama,
+                    placement: Placement::BeforePlte,
+                },
+                Violation::IdatNotConsecutive { index: 6 },
+                Violation::IendNotLast { index: 7 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_after_idat() {
+        let png = png(&["IHDR", "IDAT", "PLTE", "bKGD", "IEND"]);
+        let violations = png.validate();
+
+        assert_eq!(violations.len(), 2);
+        assert!(violations.iter().all(|violation| matches!(
+            violation,
+            Violation::Misplaced {
+                placement: Placement::BeforeIdat,
+                ..
+            }
+        )));
+        assert_eq!(
+            violations[1].to_string(),
+            "chunk 3: bKGD has to come before the first IDAT"
+        );
+    }
+
+    #[test]
+    fn test_duplicates_and_palette() {
+        let png = png(&["IHDR", "IHDR", "gAMA", "gAMA", "IDAT", "IEND"]);
+        assert_eq!(
+            png.validate(),
+            vec![
+                Violation::Duplicate {
+                    index: 1,
+                    chunk_type: ChunkType::from_str("IHDR").unwrap(),
+                },
+                Violation::Duplicate {
+                    index: 3,
+                    chunk_type: ChunkType::from_str("gAMA").unwrap(),
+                },
+                Violation::Missing("PLTE"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unexpected_palette() {
+        let mut chunks = vec![chunk("PLTE"), chunk("IDAT"), chunk("IEND")];
+        let ihdr = Ihdr {
+            width: 1,
+            height: 1,
+            bit_depth: 8,
+            color_type: ColorType::Grayscale,
+            interlace: Interlace::None,
+        };
+        chunks.insert(
+            0,
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr.as_bytes()),
+        );
+
+        assert_eq!(
+            Png::from_chunks(chunks).validate(),
+            vec![Violation::UnexpectedPlte {
+                index: 1,
+                color_type: ColorType::Grayscale,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_invalid_ihdr() {
+        let mut png = png(&["IDAT", "IEND"]);
+        png.insert_chunk(
+            0,
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            png.validate()[..],
+            [Violation::InvalidIhdr(IhdrError::BadDimensions { .. })]
+        ));
+    }
+}